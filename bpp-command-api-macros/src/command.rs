@@ -0,0 +1,200 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{bracketed, parse_quote, Error, FnArg, Ident, ItemFn, LitBool, LitStr, Pat, PathArguments, Result, Token, Type};
+
+/// The arguments of `#[bpp_command(...)]`.
+#[derive(Default)]
+pub struct CommandAttribute {
+    name: Option<LitStr>,
+    aliases: Vec<LitStr>,
+    description: Option<LitStr>,
+    usage: Option<LitStr>,
+    examples: Vec<LitStr>,
+    category: Option<LitStr>,
+    hidden: bool,
+    permissions: Vec<LitStr>,
+    ranks: Vec<LitStr>,
+    min_rank: Option<LitStr>,
+    groups: Vec<LitStr>,
+}
+
+fn parse_string_list(input: ParseStream) -> Result<Vec<LitStr>> {
+    let content;
+    bracketed!(content in input);
+    let list = Punctuated::<LitStr, Token![,]>::parse_terminated(&content)?;
+    Ok(list.into_iter().collect())
+}
+
+impl Parse for CommandAttribute {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut attribute = CommandAttribute::default();
+        while !input.is_empty() {
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            match key.to_string().as_str() {
+                "name" => attribute.name = Some(input.parse()?),
+                "aliases" => attribute.aliases = parse_string_list(input)?,
+                "description" => attribute.description = Some(input.parse()?),
+                "usage" => attribute.usage = Some(input.parse()?),
+                "examples" => attribute.examples = parse_string_list(input)?,
+                "category" => attribute.category = Some(input.parse()?),
+                "hidden" => attribute.hidden = input.parse::<LitBool>()?.value,
+                "permissions" => attribute.permissions = parse_string_list(input)?,
+                "ranks" => attribute.ranks = parse_string_list(input)?,
+                "min_rank" => attribute.min_rank = Some(input.parse()?),
+                "groups" => attribute.groups = parse_string_list(input)?,
+                _ => return Err(Error::new(key.span(), format!("unknown argument `{}`", key))),
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(attribute)
+    }
+}
+
+fn quote_option(value: &Option<LitStr>) -> TokenStream {
+    match value {
+        Some(value) => quote! { ::std::option::Option::Some(::std::string::ToString::to_string(#value)) },
+        None => quote! { ::std::option::Option::None },
+    }
+}
+
+fn quote_list(values: &[LitStr]) -> TokenStream {
+    quote! { ::std::vec![#(::std::string::ToString::to_string(#values)),*] }
+}
+
+/// Adds anonymous lifetimes to `ServiceDirectory` and `CommandContext`, which async functions don't allow to be elided.
+fn add_elided_lifetimes(ty: &mut Type) {
+    match ty {
+        Type::Reference(reference) => add_elided_lifetimes(&mut reference.elem),
+        Type::Path(path) => {
+            if let Some(segment) = path.path.segments.last_mut() {
+                if !segment.arguments.is_empty() {
+                    return;
+                }
+                if segment.ident == "ServiceDirectory" {
+                    segment.arguments = PathArguments::AngleBracketed(parse_quote! { <'_> });
+                } else if segment.ident == "CommandContext" {
+                    segment.arguments = PathArguments::AngleBracketed(parse_quote! { <'_, '_> });
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn expand(attribute: CommandAttribute, function: ItemFn) -> Result<TokenStream> {
+    if function.sig.asyncness.is_none() {
+        return Err(Error::new_spanned(function.sig.fn_token, "#[bpp_command] can only be used on async functions"));
+    }
+    let parameters: Vec<&FnArg> = function.sig.inputs.iter().collect();
+    if parameters.len() != 1 && parameters.len() != 2 {
+        return Err(Error::new_spanned(&function.sig.inputs, "expected the parameters `(context: CommandContext)` or `(message: Message, service_directory: &mut ServiceDirectory)`"));
+    }
+    for parameter in parameters.iter() {
+        if let FnArg::Typed(typed) = parameter {
+            if !matches!(*typed.pat, Pat::Ident(_)) {
+                return Err(Error::new_spanned(&typed.pat, "#[bpp_command] parameters must be plain identifiers"));
+            }
+        } else {
+            return Err(Error::new_spanned(parameter, "#[bpp_command] can only be used on free functions"));
+        }
+    }
+
+    let ident = &function.sig.ident;
+    let visibility = &function.vis;
+    let name = attribute.name.clone().unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+    let aliases = &attribute.aliases;
+    let description = quote_option(&attribute.description);
+    let usage = quote_option(&attribute.usage);
+    let category = quote_option(&attribute.category);
+    let examples = quote_list(&attribute.examples);
+    let hidden = attribute.hidden;
+    let permissions = quote_list(&attribute.permissions);
+    let ranks = quote_list(&attribute.ranks);
+    let min_rank = quote_option(&attribute.min_rank);
+    let groups = quote_list(&attribute.groups);
+
+    // The function is moved into the command struct, which takes over its name.
+    let mut handler = function.clone();
+    handler.sig.ident = format_ident!("handle");
+    handler.vis = syn::Visibility::Inherited;
+    handler.attrs.retain(|attr| !attr.path.is_ident("doc"));
+    for parameter in handler.sig.inputs.iter_mut() {
+        if let FnArg::Typed(typed) = parameter {
+            add_elided_lifetimes(&mut typed.ty);
+        }
+    }
+    let docs = function.attrs.iter().filter(|attr| attr.path.is_ident("doc"));
+
+    // Functions taking a single parameter receive the context, functions taking two use the older execute signature.
+    let command_impl = if parameters.len() == 1 {
+        quote! {
+            #[::bpp_command_api::async_trait::async_trait]
+            impl ::bpp_command_api::traits::Command for #ident {
+                async fn run(
+                    &self,
+                    context: ::bpp_command_api::context::CommandContext<'_, '_>,
+                ) -> ::std::result::Result<(), ::bpp_command_api::CommandError> {
+                    #ident::handle(context).await
+                }
+            }
+        }
+    } else {
+        quote! {
+            #[::bpp_command_api::async_trait::async_trait]
+            impl ::bpp_command_api::traits::LegacyCommand for #ident {
+                async fn execute(
+                    &self,
+                    message: ::bpp_command_api::structs::Message,
+                    service_directory: &mut ::bpp_command_api::structs::ServiceDirectory,
+                ) -> ::std::result::Result<(), ::bpp_command_api::CommandError> {
+                    #ident::handle(message, service_directory).await
+                }
+            }
+        }
+    };
+
+    Ok(quote! {
+        #(#docs)*
+        #[allow(non_camel_case_types)]
+        #[derive(Clone, Copy, Default)]
+        #visibility struct #ident;
+
+        impl #ident {
+            #handler
+        }
+
+        #command_impl
+
+        impl ::bpp_command_api::traits::CommandDefinition for #ident {
+            fn name() -> &'static str {
+                #name
+            }
+
+            fn aliases() -> &'static [&'static str] {
+                &[#(#aliases),*]
+            }
+
+            fn meta() -> ::bpp_command_api::structs::CommandMeta {
+                ::bpp_command_api::structs::CommandMeta {
+                    description: #description,
+                    usage: #usage,
+                    examples: #examples,
+                    category: #category,
+                    hidden: #hidden,
+                    requirements: ::bpp_command_api::permissions::Requirements {
+                        permissions: #permissions,
+                        ranks: #ranks,
+                        groups: #groups,
+                        min_rank: #min_rank,
+                    },
+                    ..::std::default::Default::default()
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,60 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields, Result};
+
+pub fn expand(input: DeriveInput) -> Result<TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new_spanned(&input.ident, "CommandArgs can only be derived for structs with named fields")),
+        },
+        _ => return Err(Error::new_spanned(&input.ident, "CommandArgs can only be derived for structs")),
+    };
+
+    let mut parse_fields = Vec::new();
+    let mut usages = Vec::new();
+    let mut names = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let name = ident.to_string();
+        let is_rest = field.attrs.iter().any(|attr| attr.path.is_ident("rest"));
+        if is_rest && i != fields.len() - 1 {
+            return Err(Error::new_spanned(field, "only the last field can be marked with #[rest]"));
+        }
+
+        if is_rest {
+            parse_fields.push(quote! {
+                let #ident = reader.parse_rest::<#ty>(#name)?;
+            });
+            usages.push(quote! {
+                ::bpp_command_api::args::rest_usage::<#ty>(#name)
+            });
+        } else {
+            parse_fields.push(quote! {
+                let #ident = reader.parse::<#ty>(#name)?;
+            });
+            usages.push(quote! {
+                <#ty as ::bpp_command_api::args::FromArgument>::usage(#name)
+            });
+        }
+        names.push(ident);
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::bpp_command_api::args::CommandArgs for #ident #ty_generics #where_clause {
+            fn from_reader(mut reader: ::bpp_command_api::args::ArgumentReader<'_>) -> ::std::result::Result<Self, ::bpp_command_api::args::ArgumentError> {
+                #(#parse_fields)*
+                reader.finish()?;
+                ::std::result::Result::Ok(#ident { #(#names),* })
+            }
+
+            fn usage() -> ::std::string::String {
+                let usages: ::std::vec::Vec<::std::string::String> = ::std::vec![#(#usages),*];
+                usages.join(" ")
+            }
+        }
+    })
+}
@@ -0,0 +1,40 @@
+//! Procedural macros for bpp-command-api.
+//!
+//! These macros are re-exported by bpp-command-api and should be used through it.
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput, ItemFn};
+
+mod command;
+mod command_args;
+
+/// Derives `CommandArgs` for a struct with named fields.
+///
+/// Fields are parsed in declaration order using their `FromArgument` implementation. The last
+/// field may be marked with `#[rest]` to receive the rest of the message.
+#[proc_macro_derive(CommandArgs, attributes(rest))]
+pub fn derive_command_args(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match command_args::expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// Turns an async function into a command that can be exported with `export_commands!`.
+///
+/// The function either takes a `CommandContext`, or a `Message` and a `&mut ServiceDirectory`,
+/// in which case the command implements `LegacyCommand`.
+///
+/// The function is replaced by a unit struct of the same name that implements `Command` and
+/// `CommandDefinition`. Supported arguments are `name`, `aliases`, `description`, `usage`,
+/// `examples`, `category`, `hidden`, `permissions`, `ranks`, `min_rank` and `groups`.
+#[proc_macro_attribute]
+pub fn bpp_command(attribute: TokenStream, item: TokenStream) -> TokenStream {
+    let attribute = parse_macro_input!(attribute as command::CommandAttribute);
+    let function = parse_macro_input!(item as ItemFn);
+    match command::expand(attribute, function) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
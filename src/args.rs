@@ -0,0 +1,366 @@
+use custom_error::custom_error;
+
+custom_error! { pub ArgumentError
+    Missing{position: usize, name: String} = "Missing argument {position} ({name})",
+    Invalid{position: usize, name: String, value: String, expected: String} = "Argument {position} ({name}) should be {expected}, but was '{value}'",
+    Unexpected{position: usize, value: String} = "Unexpected argument {position}: '{value}'"
+}
+
+/// Types that can be parsed from command arguments.
+///
+/// Most types only need to implement [`from_argument`](FromArgument::from_argument); types that
+/// consume a varying number of arguments override [`extract`](FromArgument::extract) as well.
+pub trait FromArgument: Sized {
+    /// Parses a single argument, returning a description of the expected input on failure (e.g. "a number").
+    fn from_argument(argument: &str) -> Result<Self, String>;
+
+    /// Takes this type's arguments from `reader`. `name` is the name of the parameter, used for error messages.
+    fn extract(reader: &mut ArgumentReader, name: &str) -> Result<Self, ArgumentError> {
+        let position = reader.position();
+        match reader.next_raw() {
+            Some(argument) => Self::from_argument(argument).map_err(|expected| ArgumentError::Invalid {
+                position,
+                name: name.to_string(),
+                value: argument.to_string(),
+                expected,
+            }),
+            None => Err(ArgumentError::Missing { position, name: name.to_string() }),
+        }
+    }
+
+    /// Renders the parameter for usage strings.
+    fn usage(name: &str) -> String {
+        format!("<{}>", name)
+    }
+}
+
+/// Types that can be parsed from all arguments of a command.
+///
+/// This is usually derived with `#[derive(CommandArgs)]`, which parses the fields of a struct in order.
+/// The last field can be marked with `#[rest]` to receive the rest of the message.
+///
+/// # Example
+///
+/// ```
+/// use bpp_command_api::CommandArgs;
+///
+/// #[derive(CommandArgs)]
+/// struct GiveArgs {
+///     target: String,
+///     amount: f64,
+///     #[rest]
+///     reason: Option<String>,
+/// }
+/// ```
+pub trait CommandArgs: Sized {
+    /// Parses all arguments of `reader`, failing if any are left over.
+    fn from_reader(reader: ArgumentReader<'_>) -> Result<Self, ArgumentError>;
+
+    /// Parses all arguments, failing if any are left over.
+    fn from_args(args: &[String]) -> Result<Self, ArgumentError> {
+        Self::from_reader(ArgumentReader::new(args))
+    }
+
+    /// Renders a usage string such as `<target> <amount> [reason...]`.
+    fn usage() -> String;
+}
+
+/// Reads typed arguments from a list of command arguments, usually [`Message::command_args`](crate::structs::Message::command_args).
+pub struct ArgumentReader<'a> {
+    args: &'a [String],
+    index: usize,
+    /// Number of arguments that came before `args`, for error positions
+    offset: usize,
+    /// The 1-based position of each of `args`, if they are not consecutive
+    positions: &'a [usize],
+}
+
+impl<'a> ArgumentReader<'a> {
+    pub fn new(args: &'a [String]) -> Self {
+        ArgumentReader { args, index: 0, offset: 0, positions: &[] }
+    }
+
+    /// Creates a reader for arguments that were taken from a longer list, such as positional
+    /// arguments between options, reporting errors at `positions` instead.
+    pub fn with_positions(args: &'a [String], positions: &'a [usize]) -> Self {
+        ArgumentReader { args, index: 0, offset: 0, positions }
+    }
+
+    /// The 1-based position of the next argument.
+    pub fn position(&self) -> usize {
+        match self.positions.get(self.index) {
+            Some(position) => *position,
+            // missing arguments are counted on from the last one
+            None => self.positions.last().copied().unwrap_or(self.offset) + self.index - self.positions.len() + 1,
+        }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.args.len() - self.index
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    pub fn peek(&self) -> Option<&'a str> {
+        self.args.get(self.index).map(|arg| arg.as_str())
+    }
+
+    pub fn next_raw(&mut self) -> Option<&'a str> {
+        let result = self.peek();
+        if result.is_some() {
+            self.index += 1;
+        }
+        result
+    }
+
+    /// Takes all remaining arguments.
+    pub fn rest(&mut self) -> &'a [String] {
+        let result = &self.args[self.index..];
+        self.index = self.args.len();
+        result
+    }
+
+    /// Parses the next parameter.
+    pub fn parse<T: FromArgument>(&mut self, name: &str) -> Result<T, ArgumentError> {
+        T::extract(self, name)
+    }
+
+    /// Joins all remaining arguments by spaces and parses them as a single parameter.
+    ///
+    /// If no arguments are left, the parameter is parsed from nothing, so `Option<T>` results in `None`.
+    pub fn parse_rest<T: FromArgument>(&mut self, name: &str) -> Result<T, ArgumentError> {
+        let offset = self.position() - 1;
+        let rest = self.rest();
+        let joined: Vec<String> = if rest.is_empty() { Vec::new() } else { vec![rest.join(" ")] };
+        let mut reader = ArgumentReader { args: &joined, index: 0, offset, positions: &[] };
+        T::extract(&mut reader, name)
+    }
+
+    /// Fails if there are arguments left that were not consumed.
+    pub fn finish(&self) -> Result<(), ArgumentError> {
+        match self.peek() {
+            Some(value) => Err(ArgumentError::Unexpected {
+                position: self.position(),
+                value: value.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+macro_rules! impl_from_argument {
+    ($expected:expr => $($t:ty),*) => {
+        $(
+            impl FromArgument for $t {
+                fn from_argument(argument: &str) -> Result<Self, String> {
+                    argument.parse().map_err(|_| $expected.to_string())
+                }
+            }
+        )*
+    };
+}
+
+impl_from_argument!("a whole number" => i8, i16, i32, i64, i128, isize);
+impl_from_argument!("a positive whole number" => u8, u16, u32, u64, u128, usize);
+impl_from_argument!("a single character" => char);
+
+macro_rules! impl_from_argument_float {
+    ($($t:ty),*) => {
+        $(
+            impl FromArgument for $t {
+                fn from_argument(argument: &str) -> Result<Self, String> {
+                    match argument.parse::<$t>() {
+                        Ok(number) if number.is_finite() => Ok(number),
+                        _ => Err("a number".to_string()),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_argument_float!(f32, f64);
+
+impl FromArgument for String {
+    fn from_argument(argument: &str) -> Result<Self, String> {
+        Ok(argument.to_string())
+    }
+}
+
+impl FromArgument for bool {
+    fn from_argument(argument: &str) -> Result<Self, String> {
+        match argument.to_lowercase().as_str() {
+            "true" | "yes" | "y" | "on" | "1" => Ok(true),
+            "false" | "no" | "n" | "off" | "0" => Ok(false),
+            _ => Err("yes or no".to_string()),
+        }
+    }
+}
+
+impl FromArgument for chrono::Duration {
+    /// Parses durations such as `90`, `45s`, `5m`, `1h30m` or `2d`. Plain numbers are seconds.
+    ///
+    /// Empty and negative durations are rejected.
+    fn from_argument(argument: &str) -> Result<Self, String> {
+        let expected = || "a duration like 30s, 5m or 1h30m".to_string();
+        if argument.is_empty() {
+            return Err(expected());
+        }
+        if argument.chars().all(|c| c.is_ascii_digit()) {
+            let seconds: i64 = argument.parse().map_err(|_| expected())?;
+            return seconds_to_duration(seconds).ok_or_else(expected);
+        }
+
+        let mut seconds: i64 = 0;
+        let mut number = String::new();
+        for c in argument.chars() {
+            if c.is_ascii_digit() {
+                number.push(c);
+                continue;
+            }
+            let amount: i64 = number.parse().map_err(|_| expected())?;
+            number.clear();
+            let unit: i64 = match c.to_ascii_lowercase() {
+                's' => 1,
+                'm' => 60,
+                'h' => 60 * 60,
+                'd' => 24 * 60 * 60,
+                'w' => 7 * 24 * 60 * 60,
+                _ => return Err(expected()),
+            };
+            seconds = amount.checked_mul(unit)
+                .and_then(|amount| seconds.checked_add(amount))
+                .ok_or_else(expected)?;
+        }
+        if !number.is_empty() {
+            return Err(expected());
+        }
+        seconds_to_duration(seconds).ok_or_else(expected)
+    }
+}
+
+/// Converts seconds into a duration, without panicking on values chrono can't represent.
+fn seconds_to_duration(seconds: i64) -> Option<chrono::Duration> {
+    seconds.checked_mul(1000)?;
+    Some(chrono::Duration::seconds(seconds))
+}
+
+/// An optional parameter, `None` if there are no arguments left.
+impl<T: FromArgument> FromArgument for Option<T> {
+    fn from_argument(argument: &str) -> Result<Self, String> {
+        T::from_argument(argument).map(Some)
+    }
+
+    fn extract(reader: &mut ArgumentReader, name: &str) -> Result<Self, ArgumentError> {
+        if reader.is_empty() {
+            Ok(None)
+        } else {
+            T::extract(reader, name).map(Some)
+        }
+    }
+
+    fn usage(name: &str) -> String {
+        format!("[{}]", name)
+    }
+}
+
+/// A variadic parameter, consuming every remaining argument.
+impl<T: FromArgument> FromArgument for Vec<T> {
+    fn from_argument(argument: &str) -> Result<Self, String> {
+        T::from_argument(argument).map(|value| vec![value])
+    }
+
+    fn extract(reader: &mut ArgumentReader, name: &str) -> Result<Self, ArgumentError> {
+        let mut result = Vec::new();
+        while !reader.is_empty() {
+            result.push(T::extract(reader, name)?);
+        }
+        Ok(result)
+    }
+
+    fn usage(name: &str) -> String {
+        format!("[{}...]", name)
+    }
+}
+
+/// Renders a parameter that is parsed with [`ArgumentReader::parse_rest`] for usage strings.
+pub fn rest_usage<T: FromArgument>(name: &str) -> String {
+    T::usage(&format!("{}...", name))
+}
+
+/// The rest of the message, made up of all remaining arguments joined by spaces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rest(pub String);
+
+impl FromArgument for Rest {
+    fn from_argument(argument: &str) -> Result<Self, String> {
+        Ok(Rest(argument.to_string()))
+    }
+
+    fn extract(reader: &mut ArgumentReader, name: &str) -> Result<Self, ArgumentError> {
+        let position = reader.position();
+        let rest = reader.rest();
+        if rest.is_empty() {
+            return Err(ArgumentError::Missing { position, name: name.to_string() });
+        }
+        Ok(Rest(rest.join(" ")))
+    }
+
+    fn usage(name: &str) -> String {
+        format!("<{}...>", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn duration(argument: &str) -> Result<chrono::Duration, String> {
+        chrono::Duration::from_argument(argument)
+    }
+
+    #[test]
+    fn plain_numbers_are_seconds() {
+        assert_eq!(duration("90"), Ok(chrono::Duration::seconds(90)));
+        assert_eq!(duration("0"), Ok(chrono::Duration::zero()));
+    }
+
+    #[test]
+    fn units_are_added_up() {
+        assert_eq!(duration("45s"), Ok(chrono::Duration::seconds(45)));
+        assert_eq!(duration("5m"), Ok(chrono::Duration::minutes(5)));
+        assert_eq!(duration("1h30m"), Ok(chrono::Duration::minutes(90)));
+        assert_eq!(duration("2D"), Ok(chrono::Duration::days(2)));
+        assert_eq!(duration("1w1s"), Ok(chrono::Duration::seconds(7 * 24 * 60 * 60 + 1)));
+    }
+
+    #[test]
+    fn empty_durations_are_rejected() {
+        assert!(duration("").is_err());
+    }
+
+    #[test]
+    fn negative_durations_are_rejected() {
+        assert!(duration("-5").is_err());
+        assert!(duration("-5m").is_err());
+        assert!(duration("+5").is_err());
+    }
+
+    #[test]
+    fn malformed_durations_are_rejected() {
+        assert!(duration("5").is_ok());
+        assert!(duration("5x").is_err());
+        assert!(duration("m").is_err());
+        assert!(duration("1h30").is_err());
+        assert!(duration("1.5h").is_err());
+    }
+
+    #[test]
+    fn overflowing_durations_are_rejected() {
+        assert!(duration("99999999999999999999").is_err());
+        assert!(duration("9223372036854775807w").is_err());
+        assert!(duration("9223372036854775807").is_err());
+    }
+}
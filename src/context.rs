@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use chrono::{DateTime, Utc};
+
+use crate::args::ArgumentReader;
+use crate::registry::CommandRegistry;
+use crate::reply::Reply;
+use crate::structs::{Message, ServiceDirectory};
+
+/// Configuration the host provides for a command, e.g. from a config file.
+pub type PluginConfig = HashMap<String, String>;
+
+static NEXT_INVOCATION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Returns an id that is unique for every invocation during the lifetime of the process.
+pub fn next_invocation_id() -> u64 {
+    NEXT_INVOCATION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Everything a command has access to while it runs.
+pub struct CommandContext<'a, 's> {
+    /// The message that invoked the command
+    pub message: Message,
+    pub services: &'a mut ServiceDirectory<'s>,
+    /// The name or alias the command was invoked with, as typed by the user
+    pub alias: String,
+    /// The prefix the command was invoked with
+    pub prefix: String,
+    /// The primary names of the command and the subcommands that were invoked, e.g. `["bank", "deposit"]`
+    ///
+    /// Arguments that selected subcommands are removed from the message's `command_args`.
+    pub path: Vec<String>,
+    /// A handle for replying to the user that invoked the command
+    pub reply: Reply,
+    /// The registry the command was dispatched from
+    pub registry: &'a CommandRegistry,
+    /// The configuration of the command
+    pub config: Arc<PluginConfig>,
+    /// When the command was invoked
+    pub invoked_at: DateTime<Utc>,
+    /// An id that is unique for this invocation, useful to correlate log lines
+    pub invocation_id: u64,
+}
+
+impl CommandContext<'_, '_> {
+    /// Creates a reader for parsing typed arguments from the message.
+    pub fn arguments(&self) -> ArgumentReader<'_> {
+        self.message.arguments()
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::CommandError;
+use crate::permissions::PermissionResolver;
+use crate::structs::CommandUser;
+
+/// Number of tracked entries after which expired entries are pruned.
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Who shares a cooldown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CooldownScope {
+    /// Every user has their own cooldown
+    User,
+    /// All users share the same cooldown
+    Global,
+}
+
+/// How often a command may be used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CooldownKind {
+    /// Once per duration
+    Fixed(Duration),
+    /// Up to `capacity` times in a burst, regaining one use every `refill`
+    Bucket { capacity: u32, refill: Duration },
+}
+
+/// A single rate limit on a command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cooldown {
+    pub scope: CooldownScope,
+    pub kind: CooldownKind,
+}
+
+impl Cooldown {
+    pub fn per_user(duration: Duration) -> Self {
+        Cooldown { scope: CooldownScope::User, kind: CooldownKind::Fixed(duration) }
+    }
+
+    pub fn global(duration: Duration) -> Self {
+        Cooldown { scope: CooldownScope::Global, kind: CooldownKind::Fixed(duration) }
+    }
+
+    pub fn bucket(scope: CooldownScope, capacity: u32, refill: Duration) -> Self {
+        Cooldown { scope, kind: CooldownKind::Bucket { capacity, refill } }
+    }
+
+    /// The time it takes to regain a single use, and the number of uses that can be made at once.
+    fn rate(&self) -> (Duration, u32) {
+        match self.kind {
+            CooldownKind::Fixed(duration) => (duration, 1),
+            CooldownKind::Bucket { capacity, refill } => (refill, capacity.max(1)),
+        }
+    }
+}
+
+/// The cooldowns of a command.
+#[derive(Clone, Debug, Default)]
+pub struct Cooldowns {
+    /// All limits have to allow an invocation for it to go through
+    pub limits: Vec<Cooldown>,
+    /// Users holding this permission are not affected by the cooldowns
+    pub bypass_permission: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CooldownKey {
+    command: String,
+    limit: usize,
+    user: Option<String>,
+}
+
+/// Keeps track of cooldowns across invocations.
+///
+/// Every limit is tracked as a generic cell rate: each use pushes a theoretical "next free" instant
+/// forward by the refill time, and a use is refused while that instant lies more than the burst
+/// allowance in the future.
+#[derive(Default)]
+pub struct CooldownTracker {
+    next_free: Mutex<HashMap<CooldownKey, Instant>>,
+}
+
+impl CooldownTracker {
+    pub fn new() -> Self {
+        CooldownTracker::default()
+    }
+
+    /// Records an invocation of `command` by `user`, or returns [`CommandError::OnCooldown`] if one of the limits is exceeded.
+    ///
+    /// A refused invocation does not count towards any of the limits. The bypass permission is resolved with `resolver`.
+    pub fn check(&self, command: &str, cooldowns: &Cooldowns, user: &CommandUser, resolver: &PermissionResolver) -> Result<(), CommandError> {
+        self.check_at(command, cooldowns, user, resolver, Instant::now())
+    }
+
+    fn check_at(&self, command: &str, cooldowns: &Cooldowns, user: &CommandUser, resolver: &PermissionResolver, now: Instant) -> Result<(), CommandError> {
+        if cooldowns.limits.is_empty() {
+            return Ok(());
+        }
+        if let Some(permission) = cooldowns.bypass_permission.as_ref() {
+            if resolver.has_permission(user, permission) {
+                return Ok(());
+            }
+        }
+
+        let mut next_free = self.next_free.lock().unwrap();
+        let mut updates: Vec<(CooldownKey, Instant)> = Vec::new();
+        let mut retry_after: Option<Duration> = None;
+        for (index, limit) in cooldowns.limits.iter().enumerate() {
+            let key = CooldownKey {
+                command: command.to_string(),
+                limit: index,
+                user: match limit.scope {
+                    CooldownScope::User => Some(user.channel_id.clone()),
+                    CooldownScope::Global => None,
+                },
+            };
+            let (refill, capacity) = limit.rate();
+            let burst = refill * (capacity - 1);
+            let free_at = next_free.get(&key).copied().unwrap_or(now).max(now);
+            let wait = free_at - now;
+            if wait > burst {
+                retry_after = Some(retry_after.unwrap_or_default().max(wait - burst));
+            } else {
+                updates.push((key, free_at + refill));
+            }
+        }
+
+        if let Some(retry_after) = retry_after {
+            return Err(CommandError::OnCooldown { retry_after });
+        }
+        if next_free.len() > PRUNE_THRESHOLD {
+            next_free.retain(|_, free_at| *free_at > now);
+        }
+        next_free.extend(updates);
+        Ok(())
+    }
+
+    /// Forgets all cooldowns of a command and its subcommands.
+    pub fn reset(&self, command: &str) {
+        let subcommand_prefix = format!("{} ", command);
+        self.next_free.lock().unwrap()
+            .retain(|key, _| key.command != command && !key.command.starts_with(&subcommand_prefix));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::userservice::Permission;
+
+    fn cooldowns(limits: Vec<Cooldown>) -> Cooldowns {
+        Cooldowns { limits, bypass_permission: None }
+    }
+
+    fn retry_after(result: Result<(), CommandError>) -> Duration {
+        match result {
+            Err(CommandError::OnCooldown { retry_after }) => retry_after,
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("the invocation was not refused"),
+        }
+    }
+
+    #[test]
+    fn fixed_cooldown_refuses_until_it_expired() {
+        let tracker = CooldownTracker::new();
+        let cooldowns = cooldowns(vec![Cooldown::per_user(Duration::from_secs(10))]);
+        let resolver = PermissionResolver::default();
+        let alice = CommandUser::test_user("alice");
+        let start = Instant::now();
+
+        assert!(tracker.check_at("give", &cooldowns, &alice, &resolver, start).is_ok());
+        assert_eq!(retry_after(tracker.check_at("give", &cooldowns, &alice, &resolver, start)), Duration::from_secs(10));
+        assert_eq!(retry_after(tracker.check_at("give", &cooldowns, &alice, &resolver, start + Duration::from_secs(4))), Duration::from_secs(6));
+        assert!(tracker.check_at("give", &cooldowns, &alice, &resolver, start + Duration::from_secs(10)).is_ok());
+    }
+
+    #[test]
+    fn user_cooldowns_are_separate_and_global_cooldowns_are_shared() {
+        let tracker = CooldownTracker::new();
+        let per_user = cooldowns(vec![Cooldown::per_user(Duration::from_secs(10))]);
+        let global = cooldowns(vec![Cooldown::global(Duration::from_secs(10))]);
+        let resolver = PermissionResolver::default();
+        let now = Instant::now();
+
+        assert!(tracker.check_at("give", &per_user, &CommandUser::test_user("alice"), &resolver, now).is_ok());
+        assert!(tracker.check_at("give", &per_user, &CommandUser::test_user("bob"), &resolver, now).is_ok());
+
+        assert!(tracker.check_at("raffle", &global, &CommandUser::test_user("alice"), &resolver, now).is_ok());
+        assert!(tracker.check_at("raffle", &global, &CommandUser::test_user("bob"), &resolver, now).is_err());
+    }
+
+    #[test]
+    fn bucket_allows_bursts_and_refills_one_use_at_a_time() {
+        let tracker = CooldownTracker::new();
+        let cooldowns = cooldowns(vec![Cooldown::bucket(CooldownScope::User, 3, Duration::from_secs(10))]);
+        let resolver = PermissionResolver::default();
+        let alice = CommandUser::test_user("alice");
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert!(tracker.check_at("roll", &cooldowns, &alice, &resolver, start).is_ok());
+        }
+        assert_eq!(retry_after(tracker.check_at("roll", &cooldowns, &alice, &resolver, start)), Duration::from_secs(10));
+
+        let refilled = start + Duration::from_secs(10);
+        assert!(tracker.check_at("roll", &cooldowns, &alice, &resolver, refilled).is_ok());
+        assert!(tracker.check_at("roll", &cooldowns, &alice, &resolver, refilled).is_err());
+    }
+
+    #[test]
+    fn refused_invocations_do_not_count() {
+        let tracker = CooldownTracker::new();
+        let cooldowns = cooldowns(vec![
+            Cooldown::bucket(CooldownScope::User, 2, Duration::from_secs(60)),
+            Cooldown::per_user(Duration::from_secs(5)),
+        ]);
+        let resolver = PermissionResolver::default();
+        let alice = CommandUser::test_user("alice");
+        let start = Instant::now();
+
+        assert!(tracker.check_at("roll", &cooldowns, &alice, &resolver, start).is_ok());
+        // refused by the fixed cooldown, so the bucket keeps its second use
+        assert!(tracker.check_at("roll", &cooldowns, &alice, &resolver, start + Duration::from_secs(1)).is_err());
+        assert!(tracker.check_at("roll", &cooldowns, &alice, &resolver, start + Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn longest_wait_is_reported() {
+        let tracker = CooldownTracker::new();
+        let cooldowns = cooldowns(vec![Cooldown::per_user(Duration::from_secs(5)), Cooldown::global(Duration::from_secs(30))]);
+        let resolver = PermissionResolver::default();
+        let now = Instant::now();
+
+        assert!(tracker.check_at("raffle", &cooldowns, &CommandUser::test_user("alice"), &resolver, now).is_ok());
+        assert_eq!(retry_after(tracker.check_at("raffle", &cooldowns, &CommandUser::test_user("alice"), &resolver, now)), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn bypass_permission_skips_cooldowns() {
+        let tracker = CooldownTracker::new();
+        let cooldowns = Cooldowns {
+            limits: vec![Cooldown::global(Duration::from_secs(60))],
+            bypass_permission: Some("cooldown.bypass".to_string()),
+        };
+        let resolver = PermissionResolver::default();
+        let mut moderator = CommandUser::test_user("moderator");
+        moderator.permissions.push(Permission { name: "cooldown.bypass".to_string() });
+        let now = Instant::now();
+
+        assert!(tracker.check_at("raffle", &cooldowns, &CommandUser::test_user("alice"), &resolver, now).is_ok());
+        assert!(tracker.check_at("raffle", &cooldowns, &moderator, &resolver, now).is_ok());
+        assert!(tracker.check_at("raffle", &cooldowns, &CommandUser::test_user("bob"), &resolver, now).is_err());
+    }
+
+    #[test]
+    fn reset_forgets_the_command_and_its_subcommands() {
+        let tracker = CooldownTracker::new();
+        let cooldowns = cooldowns(vec![Cooldown::global(Duration::from_secs(60))]);
+        let resolver = PermissionResolver::default();
+        let alice = CommandUser::test_user("alice");
+        let now = Instant::now();
+
+        for command in ["bank", "bank deposit", "bankrupt"].iter() {
+            assert!(tracker.check_at(command, &cooldowns, &alice, &resolver, now).is_ok());
+        }
+        tracker.reset("bank");
+
+        assert!(tracker.check_at("bank", &cooldowns, &alice, &resolver, now).is_ok());
+        assert!(tracker.check_at("bank deposit", &cooldowns, &alice, &resolver, now).is_ok());
+        assert!(tracker.check_at("bankrupt", &cooldowns, &alice, &resolver, now).is_err());
+    }
+}
@@ -0,0 +1,585 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use log::error;
+use tonic::transport::Channel;
+
+use crate::args::FromArgument;
+use crate::userservice::user_service_client::UserServiceClient;
+use crate::userservice::{BppUser, UserRequest};
+
+/// An error raised while reading or changing balances.
+#[derive(Debug)]
+pub enum EconomyError {
+    InvalidAmount {
+        value: String,
+    },
+    NonPositiveAmount {
+        amount: Amount,
+    },
+    InsufficientFunds {
+        channel_id: String,
+        balance: Amount,
+        amount: Amount,
+    },
+    Overflow {
+        channel_id: String,
+    },
+    InvalidBalance {
+        channel_id: String,
+    },
+    SameUser {
+        channel_id: String,
+    },
+    ServiceFailure {
+        status: Box<tonic::Status>,
+    },
+}
+
+impl fmt::Display for EconomyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EconomyError::InvalidAmount { value } => write!(f, "'{}' is not a valid amount", value),
+            EconomyError::NonPositiveAmount { amount } => {
+                write!(f, "The amount has to be positive, but was {}", amount)
+            }
+            EconomyError::InsufficientFunds {
+                channel_id,
+                balance,
+                amount,
+            } => write!(
+                f,
+                "Insufficient funds: the balance of '{}' is {}, but {} are needed",
+                channel_id, balance, amount
+            ),
+            EconomyError::Overflow { channel_id } => write!(
+                f,
+                "The balance of '{}' would exceed the maximum amount",
+                channel_id
+            ),
+            EconomyError::InvalidBalance { channel_id } => {
+                write!(f, "The balance of '{}' is not a valid amount", channel_id)
+            }
+            EconomyError::SameUser { channel_id } => {
+                write!(f, "'{}' cannot transfer money to themselves", channel_id)
+            }
+            EconomyError::ServiceFailure { status } => {
+                write!(f, "A service call failed: {}", status)
+            }
+        }
+    }
+}
+
+impl Error for EconomyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EconomyError::ServiceFailure { status } => Some(status.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<tonic::Status> for EconomyError {
+    fn from(status: tonic::Status) -> Self {
+        EconomyError::ServiceFailure {
+            status: Box::new(status),
+        }
+    }
+}
+
+/// An amount of money in cents, so calculations never lose money to rounding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Amount(pub i64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
+    pub fn from_cents(cents: i64) -> Self {
+        Amount(cents)
+    }
+
+    pub fn cents(&self) -> i64 {
+        self.0
+    }
+
+    /// Converts a balance as stored by the userservice, rounding to whole cents.
+    pub fn from_money(money: f64) -> Option<Self> {
+        let cents = (money * 100.0).round();
+        if cents.is_finite() && cents >= i64::MIN as f64 && cents < i64::MAX as f64 {
+            Some(Amount(cents as i64))
+        } else {
+            None
+        }
+    }
+
+    /// Converts the amount into a balance as stored by the userservice.
+    pub fn to_money(self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let cents = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02}", sign, cents / 100, cents % 100)
+    }
+}
+
+impl FromStr for Amount {
+    type Err = EconomyError;
+
+    /// Parses amounts such as `12`, `12.5`, `0.05` or `-3.20`, with at most two decimal places.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || EconomyError::InvalidAmount {
+            value: s.to_string(),
+        };
+        let (negative, number) = match s.trim().strip_prefix('-') {
+            Some(number) => (true, number),
+            None => (false, s.trim()),
+        };
+        let (whole, fraction) = match number.find('.') {
+            Some(dot) => (&number[..dot], &number[dot + 1..]),
+            None => (number, ""),
+        };
+        if whole.is_empty() || !whole.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        if fraction.len() > 2
+            || !fraction.chars().all(|c| c.is_ascii_digit())
+            || (number.contains('.') && fraction.is_empty())
+        {
+            return Err(invalid());
+        }
+
+        let whole: i64 = whole.parse().map_err(|_| invalid())?;
+        let fraction: i64 = format!("{:0<2}", fraction).parse().map_err(|_| invalid())?;
+        let cents = whole
+            .checked_mul(100)
+            .and_then(|cents| cents.checked_add(fraction))
+            .ok_or_else(invalid)?;
+        Ok(Amount(if negative { -cents } else { cents }))
+    }
+}
+
+impl FromArgument for Amount {
+    fn from_argument(argument: &str) -> Result<Self, String> {
+        argument
+            .parse()
+            .map_err(|_| "an amount like 10 or 2.50".to_string())
+    }
+}
+
+/// Where [`transfer_balances`] reads and writes balances.
+#[async_trait]
+trait BalanceStore {
+    /// Reads a user together with their balance.
+    async fn get_user(&self, channel_id: &str) -> Result<(BppUser, Amount), EconomyError>;
+
+    /// Writes `user` back with `balance` as their new balance.
+    async fn set_balance(&self, user: BppUser, balance: Amount) -> Result<(), EconomyError>;
+}
+
+/// Moves `amount` from `from` to `to`, withdrawing from the sender before depositing to the receiver.
+///
+/// If the deposit fails, the sender's balance is restored and the error of the deposit is returned.
+/// If restoring fails as well, that failure is logged. Callers are responsible for locking both users.
+async fn transfer_balances<S: BalanceStore + Sync>(
+    store: &S,
+    from: &str,
+    to: &str,
+    amount: Amount,
+) -> Result<(Amount, Amount), EconomyError> {
+    let (sender, sender_balance) = store.get_user(from).await?;
+    let (receiver, receiver_balance) = store.get_user(to).await?;
+    let new_sender_balance = Economy::withdraw(from, sender_balance, amount)?;
+    let new_receiver_balance =
+        receiver_balance
+            .checked_add(amount)
+            .ok_or_else(|| EconomyError::Overflow {
+                channel_id: to.to_string(),
+            })?;
+
+    store
+        .set_balance(sender.clone(), new_sender_balance)
+        .await?;
+    if let Err(error) = store.set_balance(receiver, new_receiver_balance).await {
+        if let Err(rollback_error) = store.set_balance(sender, sender_balance).await {
+            error!(
+                "Unable to restore the balance of '{}' to {} after crediting '{}' failed: {}",
+                from, sender_balance, to, rollback_error
+            );
+        }
+        return Err(error);
+    }
+    Ok((new_sender_balance, new_receiver_balance))
+}
+
+/// Changes the balances of users through the userservice.
+///
+/// Updates of the same user are serialized, so concurrent commands cannot overwrite each other's
+/// changes. This only holds for updates made through clones of the same `Economy`, so hosts should
+/// create a single one and share it with every command through the [`ServiceDirectory`](crate::structs::ServiceDirectory).
+///
+/// The serialization is local to the process. The userservice only updates whole user records, so
+/// changes made by other processes between reading and writing a user, including to fields other
+/// than the balance, are overwritten.
+#[derive(Clone)]
+pub struct Economy {
+    client: UserServiceClient<Channel>,
+    /// A lock for every user whose balance is currently being changed, keyed by channel id
+    locks: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
+}
+
+impl Economy {
+    pub fn new(client: UserServiceClient<Channel>) -> Self {
+        Economy {
+            client,
+            locks: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn lock_for(&self, channel_id: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut locks = self.locks.lock().unwrap();
+        // locks that are not held or waited for anymore are dropped
+        locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+        locks.entry(channel_id.to_string()).or_default().clone()
+    }
+
+    fn check_positive(amount: Amount) -> Result<(), EconomyError> {
+        if amount <= Amount::ZERO {
+            return Err(EconomyError::NonPositiveAmount { amount });
+        }
+        Ok(())
+    }
+
+    /// The current balance of a user.
+    pub async fn balance(&self, channel_id: &str) -> Result<Amount, EconomyError> {
+        let lock = self.lock_for(channel_id);
+        let _guard = lock.lock().await;
+        Ok(self.get_user(channel_id).await?.1)
+    }
+
+    /// Adds `amount` to the balance of a user, returning the new balance.
+    pub async fn credit(&self, channel_id: &str, amount: Amount) -> Result<Amount, EconomyError> {
+        Economy::check_positive(amount)?;
+        let lock = self.lock_for(channel_id);
+        let _guard = lock.lock().await;
+
+        let (user, balance) = self.get_user(channel_id).await?;
+        let balance = balance
+            .checked_add(amount)
+            .ok_or_else(|| EconomyError::Overflow {
+                channel_id: channel_id.to_string(),
+            })?;
+        self.set_balance(user, balance).await?;
+        Ok(balance)
+    }
+
+    /// Takes `amount` from the balance of a user, returning the new balance.
+    ///
+    /// Fails with [`EconomyError::InsufficientFunds`] if the balance is less than `amount`.
+    pub async fn debit(&self, channel_id: &str, amount: Amount) -> Result<Amount, EconomyError> {
+        Economy::check_positive(amount)?;
+        let lock = self.lock_for(channel_id);
+        let _guard = lock.lock().await;
+
+        let (user, balance) = self.get_user(channel_id).await?;
+        let balance = Economy::withdraw(channel_id, balance, amount)?;
+        self.set_balance(user, balance).await?;
+        Ok(balance)
+    }
+
+    fn withdraw(channel_id: &str, balance: Amount, amount: Amount) -> Result<Amount, EconomyError> {
+        if balance < amount {
+            return Err(EconomyError::InsufficientFunds {
+                channel_id: channel_id.to_string(),
+                balance,
+                amount,
+            });
+        }
+        balance
+            .checked_sub(amount)
+            .ok_or_else(|| EconomyError::Overflow {
+                channel_id: channel_id.to_string(),
+            })
+    }
+
+    /// Moves `amount` from one user to another, returning the new balances of both.
+    ///
+    /// Both users are locked for the whole transfer, always in the same order to avoid deadlocks.
+    /// If crediting the receiver fails, the sender's balance is restored and the error of crediting
+    /// the receiver is returned. If restoring fails as well, that failure is logged.
+    pub async fn transfer(
+        &self,
+        from: &str,
+        to: &str,
+        amount: Amount,
+    ) -> Result<(Amount, Amount), EconomyError> {
+        Economy::check_positive(amount)?;
+        if from == to {
+            return Err(EconomyError::SameUser {
+                channel_id: from.to_string(),
+            });
+        }
+
+        let (first, second) = if from < to { (from, to) } else { (to, from) };
+        let first_lock = self.lock_for(first);
+        let second_lock = self.lock_for(second);
+        let _first_guard = first_lock.lock().await;
+        let _second_guard = second_lock.lock().await;
+
+        transfer_balances(self, from, to, amount).await
+    }
+}
+
+#[async_trait]
+impl BalanceStore for Economy {
+    async fn get_user(&self, channel_id: &str) -> Result<(BppUser, Amount), EconomyError> {
+        let user = self
+            .client
+            .clone()
+            .get_user(UserRequest {
+                channel_id: channel_id.to_string(),
+            })
+            .await?
+            .into_inner();
+        let balance =
+            Amount::from_money(user.money).ok_or_else(|| EconomyError::InvalidBalance {
+                channel_id: channel_id.to_string(),
+            })?;
+        Ok((user, balance))
+    }
+
+    async fn set_balance(&self, mut user: BppUser, balance: Amount) -> Result<(), EconomyError> {
+        user.money = balance.to_money();
+        self.client.clone().update_user(user).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Balances kept in memory, with a log of every attempted write.
+    #[derive(Default)]
+    struct MemoryStore {
+        balances: Mutex<HashMap<String, Amount>>,
+        writes: Mutex<Vec<(String, Amount)>>,
+        /// The writes that fail, counted from 0 in the order they are attempted
+        failing_writes: Vec<usize>,
+    }
+
+    impl MemoryStore {
+        fn new(balances: &[(&str, i64)], failing_writes: &[usize]) -> Self {
+            MemoryStore {
+                balances: Mutex::new(
+                    balances
+                        .iter()
+                        .map(|(channel_id, cents)| (channel_id.to_string(), Amount(*cents)))
+                        .collect(),
+                ),
+                writes: Mutex::default(),
+                failing_writes: failing_writes.to_vec(),
+            }
+        }
+
+        fn balance(&self, channel_id: &str) -> Amount {
+            self.balances.lock().unwrap()[channel_id]
+        }
+
+        fn writes(&self) -> Vec<(String, Amount)> {
+            self.writes.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl BalanceStore for MemoryStore {
+        async fn get_user(&self, channel_id: &str) -> Result<(BppUser, Amount), EconomyError> {
+            let user = BppUser {
+                channel_id: channel_id.to_string(),
+                ..BppUser::default()
+            };
+            Ok((user, self.balance(channel_id)))
+        }
+
+        async fn set_balance(&self, user: BppUser, balance: Amount) -> Result<(), EconomyError> {
+            let mut writes = self.writes.lock().unwrap();
+            let write = writes.len();
+            writes.push((user.channel_id.clone(), balance));
+            if self.failing_writes.contains(&write) {
+                return Err(tonic::Status::unavailable(format!("write {} failed", write)).into());
+            }
+            self.balances
+                .lock()
+                .unwrap()
+                .insert(user.channel_id, balance);
+            Ok(())
+        }
+    }
+
+    fn failed_write(result: Result<(Amount, Amount), EconomyError>) -> String {
+        match result {
+            Err(EconomyError::ServiceFailure { status }) => status.message().to_string(),
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("the transfer succeeded"),
+        }
+    }
+
+    #[tokio::test]
+    async fn transfers_withdraw_before_they_deposit() {
+        let store = MemoryStore::new(&[("alice", 1000), ("bob", 100)], &[]);
+
+        let balances = transfer_balances(&store, "alice", "bob", Amount(250))
+            .await
+            .unwrap();
+
+        assert_eq!(balances, (Amount(750), Amount(350)));
+        assert_eq!(
+            store.writes(),
+            vec![
+                ("alice".to_string(), Amount(750)),
+                ("bob".to_string(), Amount(350))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn transfers_without_enough_funds_write_nothing() {
+        let store = MemoryStore::new(&[("alice", 100), ("bob", 0)], &[]);
+
+        let result = transfer_balances(&store, "alice", "bob", Amount(250)).await;
+
+        assert!(matches!(
+            result,
+            Err(EconomyError::InsufficientFunds {
+                balance: Amount(100),
+                ..
+            })
+        ));
+        assert!(store.writes().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_failed_deposit_restores_the_sender() {
+        let store = MemoryStore::new(&[("alice", 1000), ("bob", 100)], &[1]);
+
+        let result = transfer_balances(&store, "alice", "bob", Amount(250)).await;
+
+        assert_eq!(failed_write(result), "write 1 failed");
+        assert_eq!(
+            store.writes().last(),
+            Some(&("alice".to_string(), Amount(1000)))
+        );
+        assert_eq!(store.balance("alice"), Amount(1000));
+        assert_eq!(store.balance("bob"), Amount(100));
+    }
+
+    #[tokio::test]
+    async fn a_failed_restore_keeps_the_deposit_error() {
+        let store = MemoryStore::new(&[("alice", 1000), ("bob", 100)], &[1, 2]);
+
+        let result = transfer_balances(&store, "alice", "bob", Amount(250)).await;
+
+        assert_eq!(failed_write(result), "write 1 failed");
+        assert_eq!(store.writes().len(), 3);
+        assert_eq!(store.balance("alice"), Amount(750));
+    }
+
+    fn amount(s: &str) -> Option<Amount> {
+        s.parse().ok()
+    }
+
+    #[test]
+    fn whole_and_decimal_amounts_are_parsed() {
+        assert_eq!(amount("12"), Some(Amount(1200)));
+        assert_eq!(amount("12.5"), Some(Amount(1250)));
+        assert_eq!(amount("12.05"), Some(Amount(1205)));
+        assert_eq!(amount("0.05"), Some(Amount(5)));
+        assert_eq!(amount(" 7 "), Some(Amount(700)));
+    }
+
+    #[test]
+    fn signs_are_parsed() {
+        assert_eq!(amount("-3.20"), Some(Amount(-320)));
+        assert_eq!(amount("-0"), Some(Amount::ZERO));
+        assert_eq!(amount("+3"), None);
+        assert_eq!(amount("--3"), None);
+        assert_eq!(amount("- 3"), None);
+        assert_eq!(amount("-"), None);
+    }
+
+    #[test]
+    fn more_than_two_decimals_are_rejected() {
+        assert_eq!(amount("1.005"), None);
+        assert_eq!(amount("1.000"), None);
+    }
+
+    #[test]
+    fn malformed_amounts_are_rejected() {
+        for malformed in ["", ".5", "5.", "1.2.3", "1,50", "1e3", "ten", "1.-5"].iter() {
+            assert_eq!(amount(malformed), None, "{:?} was parsed", malformed);
+        }
+    }
+
+    #[test]
+    fn overflowing_amounts_are_rejected() {
+        assert_eq!(amount("92233720368547758.07"), Some(Amount(i64::MAX)));
+        assert_eq!(amount("92233720368547758.08"), None);
+        assert_eq!(amount("99999999999999999999"), None);
+    }
+
+    #[test]
+    fn money_is_rounded_to_whole_cents() {
+        assert_eq!(Amount::from_money(0.1 + 0.2), Some(Amount(30)));
+        assert_eq!(Amount::from_money(2.675), Some(Amount(268)));
+        assert_eq!(Amount::from_money(-1.004), Some(Amount(-100)));
+        assert_eq!(Amount::from_money(f64::NAN), None);
+        assert_eq!(Amount::from_money(f64::INFINITY), None);
+        assert_eq!(Amount::from_money(1e17), None);
+    }
+
+    #[test]
+    fn money_keeps_every_cent() {
+        assert_eq!(Amount(1).to_money(), 0.01);
+        assert_eq!(Amount(-320).to_money(), -3.2);
+        for cents in [
+            0,
+            1,
+            5,
+            99,
+            1205,
+            123_456_789,
+            -98_765,
+            1 << 40,
+            (1 << 53) - 1,
+        ]
+        .iter()
+        {
+            assert_eq!(
+                Amount::from_money(Amount(*cents).to_money()),
+                Some(Amount(*cents))
+            );
+        }
+    }
+
+    #[test]
+    fn amounts_are_displayed_with_two_decimals() {
+        assert_eq!(Amount(1205).to_string(), "12.05");
+        assert_eq!(Amount(-5).to_string(), "-0.05");
+        assert_eq!(Amount(i64::MIN).to_string(), "-92233720368547758.08");
+    }
+}
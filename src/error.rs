@@ -0,0 +1,257 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::args::ArgumentError;
+use crate::economy::EconomyError;
+use crate::flags::FlagError;
+use crate::message::StringViewError;
+use crate::structs::ConversionError;
+use crate::users::UserLookupError;
+
+/// The broad category of a [`CommandError`], used by hosts to decide how to react to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The user invoked the command incorrectly
+    User,
+    /// The user is not allowed to run the command
+    Permission,
+    /// The command is on cooldown
+    Cooldown,
+    /// A service the command depends on failed
+    ServiceFailure,
+    /// The command itself failed
+    Internal,
+}
+
+/// An error that occurred while dispatching or executing a command.
+#[derive(Debug)]
+pub enum CommandError {
+    ExecutionFailure {
+        message: String,
+    },
+    Other {
+        message: String,
+    },
+    User {
+        message: String,
+    },
+    /// An error along with the reply that is shown to the user instead of it
+    Replied {
+        reply: String,
+        inner: Box<CommandError>,
+    },
+    UnknownCommand {
+        name: String,
+    },
+    MissingPermission {
+        permission: String,
+    },
+    MissingRank {
+        ranks: String,
+    },
+    MissingGroup {
+        groups: String,
+    },
+    OnCooldown {
+        retry_after: std::time::Duration,
+    },
+    InvalidArguments {
+        source: ArgumentError,
+    },
+    ParseFailure {
+        source: StringViewError,
+    },
+    InvalidOptions {
+        source: FlagError,
+    },
+    InvalidUser {
+        source: ConversionError,
+    },
+    Economy {
+        source: EconomyError,
+    },
+    UserLookup {
+        source: UserLookupError,
+    },
+    ServiceFailure {
+        status: Box<tonic::Status>,
+    },
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::ExecutionFailure { message } => {
+                write!(f, "Failed to execute command: {}", message)
+            }
+            CommandError::Other { message } | CommandError::User { message } => {
+                write!(f, "{}", message)
+            }
+            CommandError::Replied { inner, .. } => write!(f, "{}", inner),
+            CommandError::UnknownCommand { name } => write!(f, "Unknown command: {}", name),
+            CommandError::MissingPermission { permission } => write!(
+                f,
+                "You need the permission '{}' to use this command",
+                permission
+            ),
+            CommandError::MissingRank { ranks } => {
+                write!(f, "This command can only be used by the ranks: {}", ranks)
+            }
+            CommandError::MissingGroup { groups } => {
+                write!(f, "This command can only be used by members of: {}", groups)
+            }
+            CommandError::OnCooldown { retry_after } => write!(
+                f,
+                "This command is on cooldown, try again in {} seconds",
+                retry_after.as_secs_f64().ceil()
+            ),
+            CommandError::InvalidArguments { source } => write!(f, "{}", source),
+            CommandError::ParseFailure { source } => {
+                write!(f, "Unable to parse the message: {}", source)
+            }
+            CommandError::InvalidOptions { source } => write!(f, "{}", source),
+            CommandError::InvalidUser { source } => write!(f, "{}", source),
+            CommandError::Economy { source } => write!(f, "{}", source),
+            CommandError::UserLookup { source } => write!(f, "{}", source),
+            CommandError::ServiceFailure { status } => {
+                write!(f, "A service call failed: {}", status)
+            }
+        }
+    }
+}
+
+// Written by hand instead of with custom_error, which cannot return boxed errors as the source
+impl Error for CommandError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CommandError::Replied { inner, .. } => Some(inner.as_ref()),
+            CommandError::InvalidArguments { source } => Some(source),
+            CommandError::ParseFailure { source } => Some(source),
+            CommandError::InvalidOptions { source } => Some(source),
+            CommandError::InvalidUser { source } => Some(source),
+            CommandError::Economy { source } => Some(source),
+            CommandError::UserLookup { source } => Some(source),
+            CommandError::ServiceFailure { status } => Some(status.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<ArgumentError> for CommandError {
+    fn from(source: ArgumentError) -> Self {
+        CommandError::InvalidArguments { source }
+    }
+}
+
+impl From<StringViewError> for CommandError {
+    fn from(source: StringViewError) -> Self {
+        CommandError::ParseFailure { source }
+    }
+}
+
+impl From<FlagError> for CommandError {
+    fn from(source: FlagError) -> Self {
+        CommandError::InvalidOptions { source }
+    }
+}
+
+impl From<ConversionError> for CommandError {
+    fn from(source: ConversionError) -> Self {
+        CommandError::InvalidUser { source }
+    }
+}
+
+impl From<EconomyError> for CommandError {
+    fn from(source: EconomyError) -> Self {
+        CommandError::Economy { source }
+    }
+}
+
+impl From<UserLookupError> for CommandError {
+    fn from(source: UserLookupError) -> Self {
+        CommandError::UserLookup { source }
+    }
+}
+
+impl From<tonic::Status> for CommandError {
+    fn from(status: tonic::Status) -> Self {
+        CommandError::ServiceFailure {
+            status: Box::new(status),
+        }
+    }
+}
+
+impl CommandError {
+    /// An error caused by the user, which is shown to them as is.
+    pub fn user<S: Into<String>>(message: S) -> Self {
+        CommandError::User {
+            message: message.into(),
+        }
+    }
+
+    /// An internal error, which is logged but not shown to the user.
+    pub fn failure<S: Into<String>>(message: S) -> Self {
+        CommandError::ExecutionFailure {
+            message: message.into(),
+        }
+    }
+
+    /// Attaches a reply for the user, leaving the error itself as the message that is logged.
+    pub fn with_reply<S: Into<String>>(self, reply: S) -> Self {
+        CommandError::Replied {
+            reply: reply.into(),
+            inner: Box::new(self),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            CommandError::ExecutionFailure { .. } | CommandError::Other { .. } => {
+                ErrorKind::Internal
+            }
+            CommandError::User { .. }
+            | CommandError::UnknownCommand { .. }
+            | CommandError::InvalidArguments { .. }
+            | CommandError::InvalidOptions { .. }
+            | CommandError::ParseFailure { .. } => ErrorKind::User,
+            CommandError::Replied { inner, .. } => inner.kind(),
+            CommandError::MissingPermission { .. }
+            | CommandError::MissingRank { .. }
+            | CommandError::MissingGroup { .. } => ErrorKind::Permission,
+            CommandError::OnCooldown { .. } => ErrorKind::Cooldown,
+            CommandError::ServiceFailure { .. } | CommandError::InvalidUser { .. } => {
+                ErrorKind::ServiceFailure
+            }
+            CommandError::Economy { source } => match source {
+                EconomyError::ServiceFailure { .. } | EconomyError::InvalidBalance { .. } => {
+                    ErrorKind::ServiceFailure
+                }
+                _ => ErrorKind::User,
+            },
+            CommandError::UserLookup { source } => match source {
+                UserLookupError::NotFound { .. } | UserLookupError::Ambiguous { .. } => {
+                    ErrorKind::User
+                }
+                UserLookupError::InvalidUser { .. } | UserLookupError::ServiceFailure { .. } => {
+                    ErrorKind::ServiceFailure
+                }
+            },
+        }
+    }
+
+    /// The text that should be shown to the user in chat, if any.
+    ///
+    /// Unknown commands have no reply, as hosts usually want to stay silent or suggest a command instead.
+    pub fn reply(&self) -> Option<String> {
+        match self {
+            CommandError::Replied { reply, .. } => Some(reply.clone()),
+            CommandError::UnknownCommand { .. } => None,
+            _ => match self.kind() {
+                ErrorKind::User | ErrorKind::Permission | ErrorKind::Cooldown => {
+                    Some(self.to_string())
+                }
+                ErrorKind::ServiceFailure | ErrorKind::Internal => None,
+            },
+        }
+    }
+}
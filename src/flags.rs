@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use custom_error::custom_error;
+
+use crate::args::{ArgumentError, ArgumentReader, CommandArgs, FromArgument};
+
+custom_error! { pub FlagError
+    UnknownOption{option: String} = "Unknown option '{option}'",
+    MissingValue{option: String} = "The option '{option}' needs a value",
+    UnexpectedValue{option: String} = "The option '{option}' does not take a value"
+}
+
+/// Describes an option a command accepts, such as `--duration 5m` or `-s`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OptionSpec {
+    /// The long name, used as `--long`
+    pub long: String,
+    /// The short name, used as `-s`
+    pub short: Option<char>,
+    /// Whether the option takes a value, or is a flag
+    pub takes_value: bool,
+}
+
+impl OptionSpec {
+    /// An option without a value, such as `--silent`.
+    pub fn flag(long: &str, short: Option<char>) -> Self {
+        OptionSpec { long: long.to_string(), short, takes_value: false }
+    }
+
+    /// An option with a value, such as `--duration 5m` or `--duration=5m`.
+    pub fn value(long: &str, short: Option<char>) -> Self {
+        OptionSpec { long: long.to_string(), short, takes_value: true }
+    }
+}
+
+/// Splits command arguments into positional arguments and options.
+///
+/// Recognizes `--long value`, `--long=value`, `-s value`, `-svalue`, clusters of short flags like
+/// `-abc`, and `--`, after which every argument is positional. A lone `-` and negative numbers
+/// such as `-5` are positional arguments, unless a digit was declared as a short option. Quoted
+/// arguments such as `"-s"` are always positional, see [`OptionParser::parse_quoted`].
+pub struct OptionParser {
+    specs: Vec<OptionSpec>,
+}
+
+impl OptionParser {
+    pub fn new(specs: Vec<OptionSpec>) -> Self {
+        OptionParser { specs }
+    }
+
+    fn find_long(&self, long: &str) -> Option<&OptionSpec> {
+        self.specs.iter().find(|spec| spec.long == long)
+    }
+
+    fn find_short(&self, short: char) -> Option<&OptionSpec> {
+        self.specs.iter().find(|spec| spec.short == Some(short))
+    }
+
+    pub fn parse(&self, args: &[String]) -> Result<ParsedArguments, FlagError> {
+        self.parse_quoted(args, &[])
+    }
+
+    /// Parses `args` like [`OptionParser::parse`], taking every argument that is marked in
+    /// `quoted` as a positional argument or option value, even if it looks like an option.
+    ///
+    /// `quoted` usually is [`Message::command_args_quoted`](crate::structs::Message::command_args_quoted).
+    pub fn parse_quoted(&self, args: &[String], quoted: &[bool]) -> Result<ParsedArguments, FlagError> {
+        let mut parsed = ParsedArguments::default();
+        let mut i = 0;
+        while i < args.len() {
+            let arg = &args[i];
+            let position = i + 1;
+            i += 1;
+
+            if quoted.get(position - 1).copied().unwrap_or(false) {
+                parsed.add_positional(position, arg.clone());
+                continue;
+            }
+
+            if arg == "--" {
+                for (offset, arg) in args[i..].iter().enumerate() {
+                    parsed.add_positional(i + offset + 1, arg.clone());
+                }
+                break;
+            }
+
+            if let Some(long) = arg.strip_prefix("--") {
+                let (name, inline_value) = match long.find('=') {
+                    Some(equals) => (&long[..equals], Some(&long[equals + 1..])),
+                    None => (long, None),
+                };
+                let spec = self.find_long(name).ok_or_else(|| FlagError::UnknownOption { option: format!("--{}", name) })?;
+                if !spec.takes_value {
+                    if inline_value.is_some() {
+                        return Err(FlagError::UnexpectedValue { option: format!("--{}", name) });
+                    }
+                    parsed.set_flag(spec);
+                } else if let Some(value) = inline_value {
+                    parsed.add_value(spec, position, value.to_string());
+                } else if i < args.len() {
+                    parsed.add_value(spec, i + 1, args[i].clone());
+                    i += 1;
+                } else {
+                    return Err(FlagError::MissingValue { option: format!("--{}", name) });
+                }
+                continue;
+            }
+
+            let shorts = match arg.strip_prefix('-') {
+                Some(shorts) if !shorts.is_empty() => shorts,
+                _ => {
+                    parsed.add_positional(position, arg.clone());
+                    continue;
+                }
+            };
+            let first = shorts.chars().next().unwrap();
+            if first.is_ascii_digit() && self.find_short(first).is_none() {
+                // negative number
+                parsed.add_positional(position, arg.clone());
+                continue;
+            }
+
+            for (offset, short) in shorts.char_indices() {
+                let spec = self.find_short(short).ok_or_else(|| FlagError::UnknownOption { option: format!("-{}", short) })?;
+                if !spec.takes_value {
+                    parsed.set_flag(spec);
+                    continue;
+                }
+
+                let attached = &shorts[offset + short.len_utf8()..];
+                if !attached.is_empty() {
+                    parsed.add_value(spec, position, attached.to_string());
+                } else if i < args.len() {
+                    parsed.add_value(spec, i + 1, args[i].clone());
+                    i += 1;
+                } else {
+                    return Err(FlagError::MissingValue { option: format!("-{}", short) });
+                }
+                break;
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Command arguments split into positional arguments and options.
+///
+/// Options are looked up by their long name, no matter if they were given by their long or short name.
+#[derive(Clone, Debug, Default)]
+pub struct ParsedArguments {
+    pub positional: Vec<String>,
+    /// The position of each of `positional` among all arguments
+    positions: Vec<usize>,
+    /// Values by option, along with the position of the argument they were taken from
+    values: HashMap<String, Vec<(usize, String)>>,
+    /// Number of times each flag was given
+    flags: HashMap<String, usize>,
+}
+
+impl ParsedArguments {
+    fn add_positional(&mut self, position: usize, arg: String) {
+        self.positional.push(arg);
+        self.positions.push(position);
+    }
+
+    fn set_flag(&mut self, spec: &OptionSpec) {
+        *self.flags.entry(spec.long.clone()).or_insert(0) += 1;
+    }
+
+    fn add_value(&mut self, spec: &OptionSpec, position: usize, value: String) {
+        self.values.entry(spec.long.clone()).or_default().push((position, value));
+    }
+
+    /// Whether the flag was given at least once.
+    pub fn flag(&self, long: &str) -> bool {
+        self.count(long) > 0
+    }
+
+    /// How often the flag was given, e.g. 3 for `-vvv`.
+    pub fn count(&self, long: &str) -> usize {
+        self.flags.get(long).copied().unwrap_or(0)
+    }
+
+    /// The last value given for an option.
+    pub fn value(&self, long: &str) -> Option<&str> {
+        self.values.get(long).and_then(|values| values.last()).map(|(_, value)| value.as_str())
+    }
+
+    /// All values given for an option, in order.
+    pub fn values(&self, long: &str) -> Vec<&str> {
+        self.values.get(long).map_or_else(Vec::new, |values| values.iter().map(|(_, value)| value.as_str()).collect())
+    }
+
+    /// Parses the last value given for an option, `None` if the option was not given.
+    pub fn get<T: FromArgument>(&self, long: &str) -> Result<Option<T>, ArgumentError> {
+        let (position, value) = match self.values.get(long).and_then(|values| values.last()) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        T::from_argument(value).map(Some).map_err(|expected| ArgumentError::Invalid {
+            position: *position,
+            name: format!("--{}", long),
+            value: value.clone(),
+            expected,
+        })
+    }
+
+    /// Creates a reader for parsing typed positional arguments.
+    ///
+    /// Errors report the position of the argument among all arguments, options included.
+    pub fn positional_arguments(&self) -> ArgumentReader<'_> {
+        ArgumentReader::with_positions(&self.positional, &self.positions)
+    }
+
+    /// Parses all positional arguments into `T`, usually a struct deriving [`CommandArgs`].
+    pub fn parse_positional<T: CommandArgs>(&self) -> Result<T, ArgumentError> {
+        T::from_reader(self.positional_arguments())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn parser() -> OptionParser {
+        OptionParser::new(vec![
+            OptionSpec::flag("silent", Some('s')),
+            OptionSpec::flag("verbose", Some('v')),
+            OptionSpec::value("duration", Some('d')),
+            OptionSpec::value("reason", None),
+        ])
+    }
+
+    #[test]
+    fn long_options_take_values_inline_or_from_the_next_argument() {
+        let parsed = parser().parse(&args(&["--duration", "5m", "someone", "--reason=spam", "--silent"])).unwrap();
+        assert_eq!(parsed.value("duration"), Some("5m"));
+        assert_eq!(parsed.value("reason"), Some("spam"));
+        assert!(parsed.flag("silent"));
+        assert_eq!(parsed.positional, args(&["someone"]));
+    }
+
+    #[test]
+    fn short_options_can_be_clustered_and_take_attached_values() {
+        let parsed = parser().parse(&args(&["-vvs", "-d5m", "-d", "10m"])).unwrap();
+        assert_eq!(parsed.count("verbose"), 2);
+        assert!(parsed.flag("silent"));
+        assert_eq!(parsed.values("duration"), vec!["5m", "10m"]);
+        assert_eq!(parsed.value("duration"), Some("10m"));
+
+        let parsed = parser().parse(&args(&["-sd", "5m"])).unwrap();
+        assert!(parsed.flag("silent"));
+        assert_eq!(parsed.value("duration"), Some("5m"));
+    }
+
+    #[test]
+    fn everything_after_a_double_dash_is_positional() {
+        let parsed = parser().parse(&args(&["-s", "--", "-v", "--duration"])).unwrap();
+        assert!(parsed.flag("silent"));
+        assert!(!parsed.flag("verbose"));
+        assert_eq!(parsed.positional, args(&["-v", "--duration"]));
+    }
+
+    #[test]
+    fn lone_dashes_and_negative_numbers_are_positional() {
+        let parsed = parser().parse(&args(&["-", "-5", "-1.5"])).unwrap();
+        assert_eq!(parsed.positional, args(&["-", "-5", "-1.5"]));
+    }
+
+    #[test]
+    fn missing_values_are_errors() {
+        assert!(matches!(parser().parse(&args(&["--duration"])), Err(FlagError::MissingValue { option }) if option == "--duration"));
+        assert!(matches!(parser().parse(&args(&["-sd"])), Err(FlagError::MissingValue { option }) if option == "-d"));
+    }
+
+    #[test]
+    fn unknown_options_and_values_for_flags_are_errors() {
+        assert!(matches!(parser().parse(&args(&["--force"])), Err(FlagError::UnknownOption { option }) if option == "--force"));
+        assert!(matches!(parser().parse(&args(&["-sx"])), Err(FlagError::UnknownOption { option }) if option == "-x"));
+        assert!(matches!(parser().parse(&args(&["--silent=yes"])), Err(FlagError::UnexpectedValue { option }) if option == "--silent"));
+    }
+
+    #[test]
+    fn quoted_arguments_are_never_options() {
+        let parsed = parser().parse_quoted(&args(&["-s", "-s", "--", "-v"]), &[false, true, true, false]).unwrap();
+        assert_eq!(parsed.count("silent"), 1);
+        assert!(parsed.flag("verbose"));
+        assert_eq!(parsed.positional, args(&["-s", "--"]));
+
+        let parsed = parser().parse_quoted(&args(&["--reason", "-v"]), &[false, true]).unwrap();
+        assert_eq!(parsed.value("reason"), Some("-v"));
+    }
+
+    #[test]
+    fn errors_report_positions_among_all_arguments() {
+        let parsed = parser().parse(&args(&["-s", "someone", "--duration", "5m", "ten"])).unwrap();
+        let mut reader = parsed.positional_arguments();
+        assert_eq!(reader.parse::<String>("target").unwrap(), "someone");
+        assert!(matches!(reader.parse::<u32>("amount"), Err(ArgumentError::Invalid { position: 5, .. })));
+        assert!(matches!(reader.parse::<u32>("times"), Err(ArgumentError::Missing { position: 6, .. })));
+
+        let parsed = parser().parse(&args(&["--duration", "soon"])).unwrap();
+        assert!(matches!(parsed.get::<chrono::Duration>("duration"), Err(ArgumentError::Invalid { position: 2, .. })));
+    }
+}
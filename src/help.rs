@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+
+use crate::MAX_CHAT_MESSAGE_LENGTH;
+use crate::registry::{CommandRegistry, RegisteredCommand};
+
+/// Category used for commands that didn't declare one.
+const DEFAULT_CATEGORY: &str = "Other";
+
+/// Shortens `text` to at most `limit` characters, marking the cut with an ellipsis.
+pub fn truncate(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text.to_string();
+    }
+    let mut result: String = text.chars().take(limit.saturating_sub(1)).collect();
+    result.push('…');
+    result
+}
+
+/// Joins `(separator, item)` pairs into as few messages as possible without exceeding `limit` characters.
+///
+/// The separator is dropped when an item starts a new message.
+fn pack<'a, I: IntoIterator<Item = (&'a str, String)>>(items: I, limit: usize) -> Vec<String> {
+    let mut messages: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+    for (separator, item) in items {
+        let item_len = item.chars().count();
+        let separator_len = separator.chars().count();
+        if current_len > 0 && current_len + separator_len + item_len > limit {
+            messages.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+        if current_len > 0 {
+            current.push_str(separator);
+            current_len += separator_len;
+        }
+        current.push_str(&truncate(&item, limit));
+        current_len += item_len.min(limit);
+    }
+    if current_len > 0 {
+        messages.push(current);
+    }
+    messages
+}
+
+impl CommandRegistry {
+    /// Renders a listing of all visible commands, grouped by category.
+    ///
+    /// The listing is split into as many chat messages as needed to stay within [`MAX_CHAT_MESSAGE_LENGTH`].
+    pub fn help_list(&self, prefix: &str) -> Vec<String> {
+        let mut categories: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for registered in self.iter().filter(|registered| !registered.meta.hidden) {
+            let category = registered.meta.category.as_deref().unwrap_or(DEFAULT_CATEGORY);
+            categories.entry(category).or_default().push(&registered.name);
+        }
+
+        let mut items: Vec<(&str, String)> = Vec::new();
+        for (category, mut names) in categories {
+            names.sort_unstable();
+            for (i, name) in names.into_iter().enumerate() {
+                if i == 0 {
+                    items.push((" | ", format!("{}: {}{}", category, prefix, name)));
+                } else {
+                    items.push((", ", format!("{}{}", prefix, name)));
+                }
+            }
+        }
+        pack(items, MAX_CHAT_MESSAGE_LENGTH)
+    }
+
+    /// Renders the help text of a single command, looked up by its name or one of its aliases.
+    ///
+    /// Subcommands are looked up by their path, such as `bank deposit`. The text is shortened to
+    /// fit into a single chat message.
+    pub fn help_text(&self, name: &str, prefix: &str) -> Option<String> {
+        let mut path = name.split_whitespace();
+        let mut registered = self.lookup(path.next()?)?;
+        // The primary names are shown rather than the aliases that were asked for
+        let mut names: Vec<&str> = vec![&registered.name];
+        for name in path {
+            registered = registered.subcommand(name)?;
+            names.push(&registered.name);
+        }
+
+        let alias_prefix = if names.len() == 1 { prefix } else { "" };
+        let text = render_help(registered, &format!("{}{}", prefix, names.join(" ")), alias_prefix, prefix);
+        Some(truncate(&text, MAX_CHAT_MESSAGE_LENGTH))
+    }
+}
+
+fn render_help(registered: &RegisteredCommand, invocation: &str, alias_prefix: &str, prefix: &str) -> String {
+    let meta = &registered.meta;
+    let mut text = invocation.to_string();
+    if let Some(usage) = meta.usage.as_ref() {
+        text.push(' ');
+        text.push_str(usage);
+    }
+    if let Some(description) = meta.description.as_ref() {
+        text.push_str(" - ");
+        text.push_str(description);
+    }
+    if !registered.aliases.is_empty() {
+        let aliases: Vec<String> = registered.aliases.iter().map(|alias| format!("{}{}", alias_prefix, alias)).collect();
+        text.push_str(" | Aliases: ");
+        text.push_str(&aliases.join(", "));
+    }
+    let subcommands: Vec<&str> = registered.subcommands.iter()
+        .filter(|subcommand| !subcommand.meta.hidden)
+        .map(|subcommand| subcommand.name.as_str())
+        .collect();
+    if !subcommands.is_empty() {
+        text.push_str(" | Subcommands: ");
+        text.push_str(&subcommands.join(", "));
+    }
+    if !meta.examples.is_empty() {
+        let examples: Vec<String> = meta.examples.iter().map(|example| format!("{}{}", prefix, example)).collect();
+        text.push_str(" | Examples: ");
+        text.push_str(&examples.join(", "));
+    }
+    text
+}
@@ -3,19 +3,39 @@ extern crate lazy_static;
 
 use traits::CommandRegistrar;
 
+pub mod loader;
+pub mod args;
+pub mod cooldown;
+pub mod help;
 pub mod log;
 pub mod macros;
 pub mod traits;
 pub mod structs;
 pub mod message;
+pub mod permissions;
+pub mod version;
+pub mod registry;
+pub mod reply;
+pub mod suggest;
+pub mod users;
+pub mod reload;
+pub mod error;
+pub mod context;
+pub mod flags;
+pub mod ranks;
+pub mod economy;
+
+pub use bpp_command_api_macros::{bpp_command, CommandArgs};
+pub use error::{CommandError, ErrorKind};
+
+#[doc(hidden)]
+pub use async_trait;
 
 pub static CORE_VERSION: &str = env!("CARGO_PKG_VERSION");
 pub static RUSTC_VERSION: &str = env!("RUSTC_VERSION");
 
-custom_error::custom_error! { pub CommandError
-    ExecutionFailure { message: &'static str } = "Failed to execute command: {}",
-    Other { message: &'static str } = "{}",
-}
+/// The maximum number of characters YouTube accepts in a single chat message.
+pub const MAX_CHAT_MESSAGE_LENGTH: usize = 200;
 
 pub mod userservice {
     tonic::include_proto!("userservice");
@@ -26,11 +46,21 @@ pub mod youtubeservice {
 }
 
 /// Information about the API that will be embedded into the library.
+///
+/// The layout is fixed and starts with the ABI version, so a host can always read the version of a
+/// plugin before it relies on anything else in the declaration.
+#[repr(C)]
 pub struct CommandDeclaration {
+    /// The API version used for compatibility checks
+    pub abi_version: version::AbiVersion,
     /// The rustc version that was used to compile API
     pub rustc_version: &'static str,
     /// The API version
     pub core_version: &'static str,
     /// The register function for registering a new command
+    // `&mut dyn CommandRegistrar` is a Rust fat pointer without a C layout. That is sound as long as
+    // the host and the plugin are compiled with the same rustc, which the loader checks through
+    // `rustc_version` before calling this function.
+    #[allow(improper_ctypes_definitions)]
     pub register: unsafe extern "C" fn(&mut dyn CommandRegistrar),
-}
\ No newline at end of file
+}
@@ -0,0 +1,201 @@
+use std::ffi::OsStr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use async_trait::async_trait;
+use custom_error::custom_error;
+use libloading::Library;
+use log::info;
+use tokio::sync::RwLock;
+
+use crate::{CommandDeclaration, CommandError, RUSTC_VERSION};
+use crate::context::CommandContext;
+use crate::structs::{CommandGroup, CommandMeta};
+use crate::traits::{Command, CommandRegistrar};
+use crate::version::{AbiVersion, Incompatibility, CORE_ABI_VERSION};
+
+custom_error! { pub LoaderError
+    OpenFailed{path: String, source: libloading::Error} = "Unable to open plugin '{path}': {source}",
+    MissingDeclaration{path: String, source: libloading::Error} = "Plugin '{path}' does not export a command declaration: {source}",
+    LegacyPlugin{path: String} = "Plugin '{path}' was built against a pre-versioned bpp-command-api; rebuild the plugin",
+    RustcVersionMismatch{path: String, plugin: String, host: String} = "Plugin '{path}' was compiled with rustc {plugin}, but the host was compiled with rustc {host}",
+    IncompatibleVersion{path: String, source: Incompatibility} = "Plugin '{path}' is incompatible: {source}"
+}
+
+/// The symbol plugins exported their declaration under before it was versioned.
+const LEGACY_DECLARATION_SYMBOL: &[u8] = b"command_declaration\0";
+
+/// A command that was registered by a plugin.
+///
+/// Holds a reference to the library the command was loaded from, so the library stays loaded
+/// for as long as the command (or any clone of it) exists.
+#[derive(Clone)]
+struct PluginCommand {
+    // Field order matters: the command has to be dropped before the library that contains its code.
+    command: Box<dyn Command>,
+    in_flight: Arc<RwLock<()>>,
+    _library: Arc<Library>,
+}
+
+#[async_trait]
+impl Command for PluginCommand {
+    async fn run(&self, context: CommandContext<'_, '_>) -> Result<(), CommandError> {
+        let _in_flight = self.in_flight.read().await;
+        self.command.run(context).await
+    }
+}
+
+/// Wraps a registrar to tie every command registered through it to a library.
+struct PluginRegistrar<'a> {
+    inner: &'a mut dyn CommandRegistrar,
+    library: Arc<Library>,
+    in_flight: Arc<RwLock<()>>,
+    commands: Vec<String>,
+}
+
+impl PluginRegistrar<'_> {
+    fn wrap(&self, command: Box<dyn Command>) -> Box<dyn Command> {
+        Box::new(PluginCommand {
+            command,
+            in_flight: self.in_flight.clone(),
+            _library: self.library.clone(),
+        })
+    }
+
+    fn wrap_group(&self, group: CommandGroup) -> CommandGroup {
+        CommandGroup {
+            command: group.command.map(|command| self.wrap(command)),
+            subcommands: group.subcommands.into_iter().map(|subcommand| self.wrap_group(subcommand)).collect(),
+            ..group
+        }
+    }
+}
+
+impl CommandRegistrar for PluginRegistrar<'_> {
+    fn register_command(&mut self, name: &str, aliases: &[&str], command: Box<dyn Command>) {
+        self.register_command_with_meta(name, aliases, command, CommandMeta::default());
+    }
+
+    fn register_command_with_meta(&mut self, name: &str, aliases: &[&str], command: Box<dyn Command>, meta: CommandMeta) -> bool {
+        let command = self.wrap(command);
+        let registered = self.inner.register_command_with_meta(name, aliases, command, meta);
+        if registered {
+            self.commands.push(name.to_string());
+        }
+        registered
+    }
+
+    fn register_group(&mut self, group: CommandGroup) -> bool {
+        let name = group.name.clone();
+        let group = self.wrap_group(group);
+        let registered = self.inner.register_group(group);
+        if registered {
+            self.commands.push(name);
+        }
+        registered
+    }
+}
+
+/// Information about a plugin that was loaded successfully.
+pub struct LoadedPlugin {
+    /// The path the plugin was loaded from
+    pub path: PathBuf,
+    /// The names of the commands the plugin registered, leaving out commands the registrar rejected
+    pub commands: Vec<String>,
+    in_flight: Arc<RwLock<()>>,
+    /// Keeps the library loaded for as long as the plugin is held, even if none of its commands are
+    _library: Arc<Library>,
+}
+
+impl LoadedPlugin {
+    /// Waits until no command of this plugin is executing.
+    pub async fn wait_idle(&self) {
+        let _idle = self.in_flight.write().await;
+    }
+}
+
+/// Loads command plugins that were exported with [`export_command!`](crate::export_command).
+pub struct PluginLoader {
+    rustc_version: &'static str,
+    abi_version: AbiVersion,
+}
+
+impl Default for PluginLoader {
+    fn default() -> Self {
+        PluginLoader {
+            rustc_version: RUSTC_VERSION,
+            abi_version: CORE_ABI_VERSION,
+        }
+    }
+}
+
+impl PluginLoader {
+    pub fn new() -> Self {
+        PluginLoader::default()
+    }
+
+    /// Loads the shared library at `path` and registers its commands into `registrar`.
+    ///
+    /// The plugin's rustc version and API version (see [`AbiVersion::check_compatible`]) are checked
+    /// before any of its code is called. The library is unloaded once the returned plugin and every command it registered are dropped.
+    ///
+    /// Loading a plugin runs code from the library, so only load plugins you trust.
+    pub fn load<P: AsRef<OsStr>>(&self, path: P, registrar: &mut dyn CommandRegistrar) -> Result<LoadedPlugin, LoaderError> {
+        let path = PathBuf::from(path.as_ref());
+        let display_path = path.display().to_string();
+
+        let library = unsafe { Library::new(&path) }.map_err(|source| LoaderError::OpenFailed {
+            path: display_path.clone(),
+            source,
+        })?;
+        let library = Arc::new(library);
+
+        let register = {
+            // Plugins built before the declaration had a fixed layout export it under a different
+            // name, so they are rejected here instead of being misread.
+            let declaration = match unsafe { library.get::<*const CommandDeclaration>(b"bpp_command_declaration\0") } {
+                Ok(declaration) => declaration,
+                Err(source) => {
+                    if unsafe { library.get::<*const u8>(LEGACY_DECLARATION_SYMBOL) }.is_ok() {
+                        return Err(LoaderError::LegacyPlugin { path: display_path });
+                    }
+                    return Err(LoaderError::MissingDeclaration {
+                        path: display_path,
+                        source,
+                    });
+                }
+            };
+            let declaration = unsafe { &**declaration };
+
+            if let Err(source) = self.abi_version.check_compatible(&declaration.abi_version) {
+                return Err(LoaderError::IncompatibleVersion {
+                    path: display_path,
+                    source,
+                });
+            }
+            if declaration.rustc_version != self.rustc_version {
+                return Err(LoaderError::RustcVersionMismatch {
+                    path: display_path,
+                    plugin: declaration.rustc_version.to_string(),
+                    host: self.rustc_version.to_string(),
+                });
+            }
+            declaration.register
+        };
+
+        let mut plugin_registrar = PluginRegistrar {
+            inner: registrar,
+            library: library.clone(),
+            in_flight: Arc::new(RwLock::new(())),
+            commands: Vec::new(),
+        };
+        unsafe { register(&mut plugin_registrar) };
+
+        info!("Loaded plugin '{}' with {} command(s)", display_path, plugin_registrar.commands.len());
+        Ok(LoadedPlugin {
+            path,
+            commands: plugin_registrar.commands,
+            in_flight: plugin_registrar.in_flight,
+            _library: library,
+        })
+    }
+}
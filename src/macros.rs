@@ -4,6 +4,8 @@
 ///
 /// ## HashMap
 /// ```
+/// # use std::collections::HashMap;
+/// # use bpp_command_api::collection;
 /// let my_hashmap: HashMap<&str, &str> = collection! {
 ///    "key" => "value",
 ///    "key2" => "value2",
@@ -12,6 +14,8 @@
 ///
 /// ## HashSet
 /// ```
+/// # use std::collections::HashSet;
+/// # use bpp_command_api::collection;
 /// let my_hashset: HashSet<&str> = collection! {
 ///    "value",
 ///    "value2",
@@ -38,15 +42,18 @@ macro_rules! collection {
 ///
 /// ```
 /// use async_trait::async_trait;
-/// use bpp_command_api::{Command, CommandRegistrar};
+/// use bpp_command_api::CommandError;
+/// use bpp_command_api::context::CommandContext;
+/// use bpp_command_api::traits::{Command, CommandRegistrar};
 ///
 /// #[derive(Clone)]
 /// pub struct AddCanCommand;
 ///
 /// #[async_trait]
 /// impl Command for AddCanCommand {
-///     async fn execute(&self, message: bpp_command_api::Message) {
+///     async fn run(&self, _context: CommandContext<'_, '_>) -> Result<(), CommandError> {
 ///         println!("Added a can!");
+///         Ok(())
 ///     }
 /// }
 ///
@@ -61,10 +68,90 @@ macro_rules! export_command {
     ($register:expr) => {
         #[doc(hidden)]
         #[no_mangle]
-        pub static command_declaration: $crate::CommandDeclaration = $crate::CommandDeclaration {
+        pub static bpp_command_declaration: $crate::CommandDeclaration = $crate::CommandDeclaration {
+            abi_version: $crate::version::CORE_ABI_VERSION,
             rustc_version: $crate::RUSTC_VERSION,
             core_version: $crate::CORE_VERSION,
             register: $register,
         };
     };
-}
\ No newline at end of file
+}
+
+/// Exports commands defined with [`#[bpp_command]`](crate::bpp_command) for them to be loaded.
+///
+/// This generates the register function and the declaration, so it replaces [`export_command!`].
+///
+/// # Example
+///
+/// ```
+/// use bpp_command_api::{bpp_command, CommandError};
+/// use bpp_command_api::context::CommandContext;
+/// use bpp_command_api::structs::{Message, ServiceDirectory};
+///
+/// #[bpp_command(name = "addcan", aliases = ["addbear"], description = "Adds a can")]
+/// async fn addcan(mut context: CommandContext) -> Result<(), CommandError> {
+///     context.reply.reply("Added a can!").await
+/// }
+///
+/// // commands with the older signature become a `LegacyCommand`
+/// #[bpp_command(name = "cans")]
+/// async fn cans(message: Message, _services: &mut ServiceDirectory) -> Result<(), CommandError> {
+///     println!("{} asked for the cans", message.user.display_name);
+///     Ok(())
+/// }
+///
+/// bpp_command_api::export_commands![addcan, cans];
+/// ```
+#[macro_export]
+macro_rules! export_commands {
+    ($($command:path),* $(,)?) => {
+        #[doc(hidden)]
+        extern "C" fn __bpp_register_commands(registrar: &mut dyn $crate::traits::CommandRegistrar) {
+            $(
+                $crate::traits::CommandRegistrar::register_command_with_meta(
+                    registrar,
+                    <$command as $crate::traits::CommandDefinition>::name(),
+                    <$command as $crate::traits::CommandDefinition>::aliases(),
+                    ::std::boxed::Box::new($command),
+                    <$command as $crate::traits::CommandDefinition>::meta(),
+                );
+            )*
+        }
+
+        $crate::export_command!(__bpp_register_commands);
+    };
+}
+
+/// Implements [`FromArgument`](crate::args::FromArgument) for an enum by mapping case-insensitive names to values.
+///
+/// # Example
+///
+/// ```
+/// #[derive(Clone, Copy)]
+/// pub enum Hand {
+///     Rock,
+///     Paper,
+///     Scissors,
+/// }
+///
+/// bpp_command_api::argument_enum!(Hand {
+///     "rock" => Hand::Rock,
+///     "paper" => Hand::Paper,
+///     "scissors" => Hand::Scissors,
+/// });
+/// ```
+#[macro_export]
+macro_rules! argument_enum {
+    ($enum:ty { $($name:literal => $value:expr),* $(,)? }) => {
+        impl $crate::args::FromArgument for $enum {
+            fn from_argument(argument: &str) -> Result<Self, String> {
+                $(
+                    if argument.eq_ignore_ascii_case($name) {
+                        return Ok($value);
+                    }
+                )*
+                Err(format!("one of {}", [$($name),*].join(", ")))
+            }
+        }
+    };
+}
@@ -4,9 +4,117 @@ use custom_error::custom_error;
 use crate::collection;
 
 custom_error! { pub StringViewError
-    ExpectedClosingQuote{close_quote: String} = "Expected closing quote '{}'",
-    UnexpectedQuote{quote: String} = "Unexpected quote '{}' in non-quoted string.",
-    InvalidEndOfQuotedString{char: String} = "Expected space after closing quotation but received {}"
+    ExpectedClosingQuote{close_quote: String, span: Span} = @{ format!("Expected closing quote '{}'", close_quote) },
+    UnexpectedQuote{quote: String, span: Span} = @{ format!("Unexpected quote '{}' in non-quoted string.", quote) },
+    InvalidEndOfQuotedString{char: String, span: Span} = @{ format!("Expected space after closing quotation but received {}", char) }
+}
+
+/// The number of characters shown on each side of the offending text in compact diagnostics.
+const DIAGNOSTIC_CONTEXT: usize = 15;
+
+/// A range of bytes in the parsed text, from `start` (inclusive) to `end` (exclusive).
+///
+/// Both ends always lie on a char boundary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Moves the span by `offset` bytes, e.g. to make it relative to a text the parsed text was taken from.
+    pub fn shifted(&self, offset: usize) -> Self {
+        Span::new(self.start + offset, self.end + offset)
+    }
+
+    /// The span as a range of characters instead of bytes.
+    pub fn char_range(&self, text: &str) -> (usize, usize) {
+        let start = text[..self.start].chars().count();
+        (start, start + text[self.start..self.end].chars().count())
+    }
+}
+
+impl StringViewError {
+    /// The part of the parsed text that caused the error.
+    pub fn span(&self) -> Span {
+        match self {
+            StringViewError::ExpectedClosingQuote { span, .. }
+            | StringViewError::UnexpectedQuote { span, .. }
+            | StringViewError::InvalidEndOfQuotedString { span, .. } => *span,
+        }
+    }
+
+    /// Moves the span of the error by `offset` bytes.
+    pub fn shifted(self, offset: usize) -> Self {
+        match self {
+            StringViewError::ExpectedClosingQuote { close_quote, span } => StringViewError::ExpectedClosingQuote { close_quote, span: span.shifted(offset) },
+            StringViewError::UnexpectedQuote { quote, span } => StringViewError::UnexpectedQuote { quote, span: span.shifted(offset) },
+            StringViewError::InvalidEndOfQuotedString { char, span } => StringViewError::InvalidEndOfQuotedString { char, span: span.shifted(offset) },
+        }
+    }
+
+    /// The error message without a trailing period, so the renderers can append to it.
+    fn summary(&self) -> String {
+        self.to_string().trim_end_matches('.').to_string()
+    }
+
+    /// Renders the error on a single line, suitable as a reply in chat.
+    ///
+    /// The offending text is enclosed in `>>` and `<<`, along with a few characters around it:
+    /// `Expected closing quote '"': !say >>"hello world<<`
+    pub fn render_compact(&self, text: &str) -> String {
+        let span = self.span();
+        let before = &text[..span.start];
+        let before_start = before.char_indices().rev().nth(DIAGNOSTIC_CONTEXT - 1).map_or(0, |(i, _)| i);
+        let after = &text[span.end..];
+        let after_end = after.char_indices().nth(DIAGNOSTIC_CONTEXT).map_or(after.len(), |(i, _)| i);
+
+        let mut offending = text[span.start..span.end].to_string();
+        if offending.chars().count() > DIAGNOSTIC_CONTEXT * 2 {
+            offending = offending.chars().take(DIAGNOSTIC_CONTEXT * 2).collect::<String>() + "…";
+        }
+
+        format!(
+            "{}: {}{}>>{}<<{}{}",
+            self.summary(),
+            if before_start > 0 { "…" } else { "" },
+            &before[before_start..],
+            offending,
+            &after[..after_end],
+            if after_end < after.len() { "…" } else { "" }
+        )
+    }
+
+    /// Renders the error over multiple lines with the offending text underlined, suitable for logs.
+    ///
+    /// ```text
+    /// Expected closing quote '"' at column 6
+    ///   | !say "hello world
+    ///   |      ^^^^^^^^^^^^
+    /// ```
+    pub fn render_verbose(&self, text: &str) -> String {
+        let (start, end) = self.span().char_range(text);
+        format!(
+            "{} at column {}\n  | {}\n  | {}{}",
+            self.summary(),
+            start + 1,
+            text,
+            " ".repeat(start),
+            "^".repeat((end - start).max(1))
+        )
+    }
 }
 
 // Below is the implementation of Rapptz's StringView in Rust.
@@ -36,54 +144,182 @@ DEALINGS IN THE SOFTWARE.
 */
 
 lazy_static! {
-    static ref QUOTE_CHARS: HashMap<String, String> = {
-        let m: HashMap<String, String> = collection! {
-            "\"".to_string() => "\"".to_string(),
-            "‘".to_string() => "’".to_string(),
-            "‚".to_string() => "‛".to_string(),
-            "“".to_string() => "”".to_string(),
-            "„".to_string() => "‟".to_string(),
-            "⹂".to_string() => "⹂".to_string(),
-            "「".to_string() => "」".to_string(),
-            "『".to_string() => "』".to_string(),
-            "〝".to_string() => "〞".to_string(),
-            "﹁".to_string() => "﹂".to_string(),
-            "﹃".to_string() => "﹄".to_string(),
-            "＂".to_string() => "＂".to_string(),
-            "｢".to_string() => "｣".to_string(),
-            "«".to_string() => "»".to_string(),
-            "‹".to_string() => "›".to_string(),
-            "《".to_string() => "》".to_string(),
-            "〈".to_string() => "〉".to_string(),
+    static ref QUOTE_CHARS: HashMap<char, char> = {
+        let m: HashMap<char, char> = collection! {
+            '"' => '"',
+            '‘' => '’',
+            '‚' => '‛',
+            '“' => '”',
+            '„' => '‟',
+            '⹂' => '⹂',
+            '「' => '」',
+            '『' => '』',
+            '〝' => '〞',
+            '﹁' => '﹂',
+            '﹃' => '﹄',
+            '＂' => '＂',
+            '｢' => '｣',
+            '«' => '»',
+            '‹' => '›',
+            '《' => '》',
+            '〈' => '〉',
         };
         m
     };
+}
 
-    static ref ALL_QUOTE_CHARS: HashSet<String> = {
-        let mut v: HashSet<String> = HashSet::new();
-        for (k, qv) in QUOTE_CHARS.iter() {
-            v.insert(k.clone());
-            v.insert(qv.clone());
+/// The prefixes that mark a chat message as a command.
+#[derive(Clone, Debug)]
+pub struct CommandPrefixes {
+    /// Prefixes that are written directly in front of the command name, such as `!` in `!addcan`
+    pub prefixes: Vec<String>,
+    /// Names that can be mentioned instead of using a prefix, such as `ByersBot` in `@ByersBot addcan`
+    pub mentions: Vec<String>,
+}
+
+impl Default for CommandPrefixes {
+    fn default() -> Self {
+        CommandPrefixes {
+            prefixes: vec!["!".to_string()],
+            mentions: Vec::new(),
         }
-        v
-    };
+    }
 }
 
+impl CommandPrefixes {
+    pub fn new(prefixes: &[&str]) -> Self {
+        CommandPrefixes {
+            prefixes: prefixes.iter().map(|prefix| prefix.to_string()).collect(),
+            mentions: Vec::new(),
+        }
+    }
+
+    /// Finds the prefix `message` starts with, returning the prefix as written and the rest of the message.
+    ///
+    /// Prefixes have to be followed directly by the command name, mentions by whitespace and the command name.
+    /// If multiple prefixes match, the longest one is used.
+    pub fn strip<'a>(&self, message: &'a str) -> Option<(&'a str, &'a str)> {
+        let message = message.trim_start();
+
+        let mut best: Option<(&'a str, &'a str)> = None;
+        for prefix in self.prefixes.iter().filter(|prefix| !prefix.is_empty()) {
+            if let Some(rest) = message.strip_prefix(prefix.as_str()) {
+                let is_longer = !matches!(best, Some((best_prefix, _)) if best_prefix.len() >= prefix.len());
+                if is_longer && rest.starts_with(|c: char| !c.is_whitespace()) {
+                    best = Some((&message[..prefix.len()], rest));
+                }
+            }
+        }
+        if best.is_some() {
+            return best;
+        }
+
+        let mentioned = message.strip_prefix('@')?;
+        for mention in self.mentions.iter() {
+            let name = match mentioned.get(..mention.len()) {
+                Some(name) => name,
+                None => continue,
+            };
+            let rest = &mentioned[mention.len()..];
+            if name.eq_ignore_ascii_case(mention) && rest.starts_with(char::is_whitespace) && !rest.trim().is_empty() {
+                return Some((&message[..mention.len() + 1], rest.trim_start()));
+            }
+        }
+        None
+    }
+}
+
+/// What to do with a quote that is never closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnclosedQuotes {
+    /// Fail with [`StringViewError::ExpectedClosingQuote`]
+    Error,
+    /// Treat the opening quote as a regular character, so `"hello world` becomes `"hello` and `world`
+    Literal,
+}
+
+/// Rules for splitting a message into (quoted) words.
+#[derive(Debug, Clone)]
+pub struct ParserConfig {
+    /// Opening quote characters mapped to their closing quote characters
+    pub quotes: HashMap<char, char>,
+    /// Whether quote characters inside an unquoted word are part of the word, instead of an error
+    ///
+    /// This also turns a quoted word that is directly followed by other characters, such as
+    /// `"hi"!`, into an unquoted word.
+    pub quotes_in_words: bool,
+    /// The character used to escape quotes, or `None` to disable escaping
+    pub escape: Option<char>,
+    pub unclosed_quotes: UnclosedQuotes,
+}
+
+impl Default for ParserConfig {
+    /// Uses common quote characters of many languages and `\` for escaping, and treats stray and
+    /// unclosed quotes as errors.
+    fn default() -> Self {
+        ParserConfig {
+            quotes: QUOTE_CHARS.clone(),
+            quotes_in_words: false,
+            escape: Some('\\'),
+            unclosed_quotes: UnclosedQuotes::Error,
+        }
+    }
+}
+
+impl ParserConfig {
+    /// A config that never fails on quotes: stray quotes are kept in words, and unclosed quotes and
+    /// closing quotes followed by other characters are taken literally.
+    pub fn lenient() -> Self {
+        ParserConfig {
+            quotes_in_words: true,
+            unclosed_quotes: UnclosedQuotes::Literal,
+            ..ParserConfig::default()
+        }
+    }
+}
+
+/// A cursor over a string for splitting it into (quoted) words.
+///
+/// All positions are byte offsets into `buffer` that always lie on a char boundary, and every
+/// operation only looks at the characters it consumes, so parsing a message is linear in its length.
 pub struct StringView {
-    pub index: i32,
+    /// The byte offset of the current character
+    pub index: usize,
     pub buffer: String,
-    pub end: i32,
-    pub previous: i32
+    /// The length of `buffer` in bytes
+    pub end: usize,
+    /// The value of `index` before the last operation
+    pub previous: usize,
+    config: ParserConfig,
+    /// Every opening and closing quote of `config`
+    quote_chars: HashSet<char>,
+    /// Opening quotes that were taken literally, with the offset their scan started at and the
+    /// offset of the closing quote, if any. Words opened by the same quote in between are taken
+    /// literally without scanning again.
+    literal_quotes: HashMap<char, (usize, Option<usize>)>,
+    /// The number of characters moved past with `get`, to check that parsing is linear
+    #[cfg(test)]
+    visited: usize,
 }
 
 impl StringView {
     pub fn new(buffer: String) -> Self {
-        let buffer_len = buffer.len() as i32;
+        StringView::with_config(buffer, ParserConfig::default())
+    }
+
+    pub fn with_config(buffer: String, config: ParserConfig) -> Self {
+        let buffer_len = buffer.len();
+        let quote_chars = config.quotes.iter().flat_map(|(open, close)| vec![*open, *close]).collect();
         StringView {
             index: 0,
             buffer,
             end: buffer_len,
-            previous: 0
+            previous: 0,
+            config,
+            quote_chars,
+            literal_quotes: HashMap::new(),
+            #[cfg(test)]
+            visited: 0,
         }
     }
 
@@ -91,207 +327,425 @@ impl StringView {
         self.index >= self.end
     }
 
+    /// The character at the current position.
     pub fn current(&self) -> Option<char> {
-        if self.eof() {
-            None
-        } else {
-            return self.buffer.chars().nth(self.index as usize);
-        }
+        self.buffer[self.index..].chars().next()
     }
 
     pub fn undo(&mut self) {
         self.index = self.previous;
     }
 
+    /// Moves past all whitespace at the current position, returning whether any was skipped.
     pub fn skip_whitespace(&mut self) -> bool {
-        let mut pos = 0;
-        while !self.eof() {
-            let current_pos = self.index + pos;
-            let current_char = self.buffer.chars().nth(current_pos as usize);
-            match current_char {
-                Some(c) => {
-                    if !c.is_whitespace() {
-                        break;
-                    }
-                    pos += 1;
-                },
-                None => {
-                    break;
-                }
-            }
-        }
+        let rest = &self.buffer[self.index..];
+        let skipped = rest.len() - rest.trim_start().len();
 
         self.previous = self.index;
-        self.index += pos;
-        self.previous != self.index
+        self.index += skipped;
+        skipped > 0
     }
 
-    pub fn skip_string(&mut self, string: String) -> bool {
-        let str_len = string.len() as i32;
-        if self.buffer[self.index as usize..(self.index + str_len) as usize].eq(string.as_str()) {
+    /// Moves past `string` if the buffer continues with it.
+    pub fn skip_string(&mut self, string: &str) -> bool {
+        if self.buffer[self.index..].starts_with(string) {
             self.previous = self.index;
-            self.index += str_len;
+            self.index += string.len();
             return true;
         }
         false
     }
 
     pub fn read_rest(&mut self) -> String {
-        let result = self.buffer[self.index as usize..].to_string();
+        let result = self.buffer[self.index..].to_string();
         self.previous = self.index;
         self.index = self.end;
         result
     }
 
-    pub fn read(&mut self, length: i32) -> String {
-        let result = self.buffer[self.index as usize..(self.index + length) as usize].to_string();
+    /// Reads up to `length` characters.
+    pub fn read(&mut self, length: usize) -> String {
+        let rest = &self.buffer[self.index..];
+        let byte_length = rest.char_indices().nth(length).map_or(rest.len(), |(i, _)| i);
+        let result = rest[..byte_length].to_string();
         self.previous = self.index;
-        self.index += length;
+        self.index += byte_length;
         result
     }
 
+    /// Moves to the next character and returns it.
     pub fn get(&mut self) -> Option<char> {
-        let result: Option<char>;
-        let index_result = self.buffer.get((self.index + 1) as usize..(self.index + 2) as usize);
-        if let Some(index_result) = index_result {
-            result = Some(index_result.chars().next().unwrap());
-        } else {
-            result = None;
-        }
+        let current = self.current()?;
         self.previous = self.index;
-        self.index += 1;
-        result
+        self.index += current.len_utf8();
+        #[cfg(test)]
+        {
+            self.visited += 1;
+        }
+        self.current()
     }
 
+    /// Reads everything up to the next whitespace.
     pub fn get_word(&mut self) -> String {
-        let mut pos = 0;
-        while !self.eof() {
-            let current_pos = self.index + pos;
-            let current_char = self.buffer.chars().nth(current_pos as usize);
-            match current_char {
-                Some(c) => {
-                    if c.is_whitespace() {
-                        break;
-                    }
-                    pos += 1;
-                },
-                None => {
-                    break;
-                }
-            }
-        }
-
+        let rest = &self.buffer[self.index..];
+        let word_length = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let result = rest[..word_length].to_string();
         self.previous = self.index;
-        let result = self.buffer[self.index as usize..(self.index + pos) as usize].to_string();
-        self.index += pos;
+        self.index += word_length;
         result
     }
 
+    /// Splits the rest of the buffer into words, treating quoted text as a single word.
     pub fn get_parameters(&mut self) -> Result<Vec<String>, StringViewError> {
-        let mut parameters: Vec<String> = Vec::new();
-        while !self.eof() {
-            let quoted_word = self.get_quoted_word();
-            if quoted_word.is_err() {
-                return Err(quoted_word.err().unwrap());
-            }
-            let quoted_word = quoted_word.unwrap();
-            if quoted_word.is_none()  {
-                break;
+        Ok(self.get_arguments()?.into_iter().map(|(word, _)| word).collect())
+    }
+
+    /// Splits the rest of the buffer like [`get_parameters`](StringView::get_parameters), along
+    /// with whether each word was enclosed in quotes.
+    pub fn get_arguments(&mut self) -> Result<Vec<(String, bool)>, StringViewError> {
+        let mut arguments: Vec<(String, bool)> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.read_word(true)? {
+                Some(argument) => arguments.push(argument),
+                None => break,
             }
-            let quoted_word = quoted_word.unwrap();
-            parameters.push(quoted_word);
         }
-        Ok(parameters)
+        Ok(arguments)
     }
 
+    /// Reads a word starting at the current position, which may be enclosed in quotes.
+    ///
+    /// Inside a word, quotes can be escaped with the escape character of the config. The escape
+    /// character in front of any other character is kept as is.
     pub fn get_quoted_word(&mut self) -> Result<Option<String>, StringViewError> {
-        let current = self.current();
-        if current.is_none() {
-            return Ok(None);
+        Ok(self.read_word(true)?.map(|(word, _)| word))
+    }
+
+    /// Whether `c` is a quote that can be escaped inside a word started by `open`.
+    fn is_escaped_quote(&self, c: char, open: char, close_quote: Option<char>) -> bool {
+        match close_quote {
+            Some(close_quote) => c == open || c == close_quote,
+            None => self.quote_chars.contains(&c),
+        }
+    }
+
+    /// Reads the word at `start` again without treating its opening quote `open` as a quote.
+    fn read_literal(&mut self, start: usize, open: char, close_at: Option<usize>) -> Result<Option<(String, bool)>, StringViewError> {
+        self.literal_quotes.insert(open, (start, close_at));
+        self.index = start;
+        self.read_word(false)
+    }
+
+    /// Reads a word and whether it was enclosed in quotes.
+    fn read_word(&mut self, allow_quoted: bool) -> Result<Option<(String, bool)>, StringViewError> {
+        let current = match self.current() {
+            Some(current) => current,
+            None => return Ok(None),
+        };
+
+        let start = self.index;
+        let close_quote = self.config.quotes.get(&current).copied().filter(|_| allow_quoted);
+        if let (Some(_), Some(&(scanned_from, close_at))) = (close_quote, self.literal_quotes.get(&current)) {
+            // the scan from `scanned_from` would end at the same closing quote
+            let same_end = match close_at {
+                Some(close_at) => start < close_at,
+                None => true,
+            };
+            if scanned_from <= start && same_end {
+                return self.read_word(false);
+            }
         }
-        let current = current.unwrap();
-
-        let close_quote = QUOTE_CHARS.get(&current.to_string());
-        let mut result: Vec<char>;
-        let _escaped_quotes: HashSet<String>;
-        let is_quoted = close_quote.is_some();
-        if is_quoted {
-            result = Vec::new();
-            let close_quote = close_quote.unwrap().chars().next().unwrap();
-            _escaped_quotes = collection! { current.to_string(), close_quote.to_string() };
-        } else {
-            result = vec![current];
-            _escaped_quotes = ALL_QUOTE_CHARS.clone();
+        let mut result = String::new();
+        if close_quote.is_none() {
+            result.push(current);
         }
+        // an unclosed quote spans everything from the opening quote to the end of the buffer
+        let end = self.end;
+        let expected_closing_quote = |close_quote: char| StringViewError::ExpectedClosingQuote {
+            close_quote: close_quote.to_string(),
+            span: Span::new(start, end),
+        };
+        let escape = self.config.escape;
+        let quotes_in_words = self.config.quotes_in_words;
+        let literal_unclosed = self.config.unclosed_quotes == UnclosedQuotes::Literal;
 
-        while !self.eof() {
-            let current_char = self.get();
-            if current_char.is_none() {
-                if is_quoted {
+        loop {
+            let current_char = match self.get() {
+                Some(current_char) => current_char,
+                None => {
                     // unexpected EOF
-                    return Err(StringViewError::ExpectedClosingQuote {
-                        close_quote: close_quote.unwrap().clone()
-                    });
-                }
-                return Ok(Some(result.into_iter().collect()));
-            }
-            let current_char = current_char.unwrap();
-            if current_char == '\\' {
-                let next_char = self.get();
-                if next_char.is_none() {
-                    // string ends with escape without character behind it
-                    if is_quoted {
-                        // we expect a closing quote
-                        return Err(StringViewError::ExpectedClosingQuote {
-                            close_quote: close_quote.unwrap().clone()
-                        });
+                    if let Some(close_quote) = close_quote {
+                        if literal_unclosed {
+                            return self.read_literal(start, current, None);
+                        }
+                        return Err(expected_closing_quote(close_quote));
                     }
-                    return Ok(Some(result.into_iter().collect()));
+                    return Ok(Some((result, close_quote.is_some())));
                 }
-
-                let next_char = next_char.unwrap();
-                if _escaped_quotes.contains(&next_char.to_string()) {
-                    // escaped quote
-                    result.push(next_char);
-                } else {
-                    // escaped character
-                    // ignoring it
-                    self.undo();
-                    result.push(current_char);
+            };
+
+            if Some(current_char) == escape {
+                let next_char = self.buffer[self.index + current_char.len_utf8()..].chars().next();
+                match next_char {
+                    None => {
+                        // string ends with escape without character behind it
+                        if let Some(close_quote) = close_quote {
+                            if literal_unclosed {
+                                return self.read_literal(start, current, None);
+                            }
+                            // we expect a closing quote
+                            return Err(expected_closing_quote(close_quote));
+                        }
+                        self.get();
+                        return Ok(Some((result, close_quote.is_some())));
+                    }
+                    Some(next_char) if self.is_escaped_quote(next_char, current, close_quote) => {
+                        // escaped quote
+                        self.get();
+                        result.push(next_char);
+                    }
+                    Some(_) => {
+                        // escaped character
+                        // ignoring it
+                        result.push(current_char);
+                    }
                 }
                 continue;
             }
 
-            if !is_quoted && ALL_QUOTE_CHARS.contains(&current_char.to_string()) {
+            if close_quote.is_none() && !quotes_in_words && self.quote_chars.contains(&current_char) {
                 // unexpected quote
                 return Err(StringViewError::UnexpectedQuote {
-                    quote: current_char.to_string()
+                    quote: current_char.to_string(),
+                    span: Span::new(self.index, self.index + current_char.len_utf8()),
                 });
             }
 
             // closing quote
-            if is_quoted {
-                let close_quote = close_quote.unwrap().chars().next().unwrap();
-                if current_char == close_quote {
-                    let next_char = self.get();
-                    let valid_eof = next_char.is_none() || next_char.unwrap().is_whitespace();
-                    if !valid_eof {
-                        return Err(StringViewError::InvalidEndOfQuotedString {
-                            char: next_char.unwrap().to_string()
-                        });
+            if Some(current_char) == close_quote {
+                let close_at = self.index;
+                let next_char = self.get();
+                if let Some(next_char) = next_char.filter(|c| !c.is_whitespace()) {
+                    if quotes_in_words {
+                        return self.read_literal(start, current, Some(close_at));
                     }
-
-                    return Ok(Some(result.into_iter().collect()));
+                    return Err(StringViewError::InvalidEndOfQuotedString {
+                        char: next_char.to_string(),
+                        span: Span::new(self.index, self.index + next_char.len_utf8()),
+                    });
                 }
+                return Ok(Some((result, close_quote.is_some())));
             }
 
-            if current_char.is_whitespace() && !is_quoted {
+            if current_char.is_whitespace() && close_quote.is_none() {
                 // end of word
-                return Ok(Some(result.into_iter().collect()));
+                return Ok(Some((result, close_quote.is_some())));
             }
             result.push(current_char);
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn quote_pairs() -> Vec<(char, char)> {
+        let mut pairs: Vec<(char, char)> = QUOTE_CHARS.iter().map(|(open, close)| (*open, *close)).collect();
+        pairs.sort_unstable();
+        pairs
+    }
+
+    /// Quotes `content` with `open` and `close`, escaping quotes inside of it.
+    fn quote(content: &str, open: char, close: char) -> String {
+        let mut quoted = open.to_string();
+        for c in content.chars() {
+            if c == open || c == close {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        quoted.push(close);
+        quoted
+    }
+
+    /// Reads every word of `text`, checking that the parser only ever stops on char boundaries.
+    fn check_boundaries(text: &str, config: ParserConfig) {
+        let mut view = StringView::with_config(text.to_string(), config);
+        loop {
+            view.skip_whitespace();
+            let before = view.index;
+            match view.get_quoted_word() {
+                Ok(Some(_)) => {
+                    assert!(view.index > before, "no progress at {} in {:?}", before, text);
+                    assert!(text.is_char_boundary(view.index));
+                }
+                Ok(None) => break,
+                Err(error) => {
+                    let span = error.span();
+                    assert!(span.start <= span.end && span.end <= text.len());
+                    assert!(text.is_char_boundary(span.start) && text.is_char_boundary(span.end));
+                    error.render_compact(text);
+                    error.render_verbose(text);
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn words_are_split_at_whitespace() {
+        let mut view = StringView::new("give  someone\t10 ".to_string());
+        assert_eq!(view.get_parameters().unwrap(), vec!["give", "someone", "10"]);
+    }
+
+    #[test]
+    fn quoted_words_are_marked() {
+        let mut view = StringView::new("-s \"-s\" «»".to_string());
+        let expected = vec![("-s".to_string(), false), ("-s".to_string(), true), (String::new(), true)];
+        assert_eq!(view.get_arguments().unwrap(), expected);
+    }
+
+    #[test]
+    fn stray_and_unclosed_quotes_are_errors_by_default() {
+        assert!(StringView::new("a\"b".to_string()).get_parameters().is_err());
+        assert!(StringView::new("\"hello world".to_string()).get_parameters().is_err());
+        assert!(StringView::new("\"hello\"world".to_string()).get_parameters().is_err());
+    }
+
+    #[test]
+    fn lenient_parsing_takes_malformed_quotes_literally() {
+        let parameters = |text: &str| StringView::with_config(text.to_string(), ParserConfig::lenient()).get_parameters().unwrap();
+        assert_eq!(parameters("say \"hi\"!"), vec!["say", "\"hi\"!"]);
+        assert_eq!(parameters("say \"hello world\"!"), vec!["say", "\"hello", "world\"!"]);
+        assert_eq!(parameters("say \"hello world"), vec!["say", "\"hello", "world"]);
+        assert_eq!(parameters("say \"a \"b"), vec!["say", "\"a", "\"b"]);
+        assert_eq!(parameters("say «a «b» c"), vec!["say", "a «b", "c"]);
+        assert_eq!(parameters("say \"hello world\""), vec!["say", "hello world"]);
+    }
+
+    #[test]
+    fn the_longest_matching_prefix_is_stripped() {
+        let prefixes = CommandPrefixes::new(&["!", "!!", "b!"]);
+        assert_eq!(prefixes.strip("!!give 10"), Some(("!!", "give 10")));
+        assert_eq!(prefixes.strip("!give 10"), Some(("!", "give 10")));
+        assert_eq!(prefixes.strip("  b!give"), Some(("b!", "give")));
+        assert_eq!(CommandPrefixes::new(&["!!", "!"]).strip("!!!give"), Some(("!!", "!give")));
+    }
+
+    #[test]
+    fn a_prefix_alone_is_not_a_command() {
+        let mut prefixes = CommandPrefixes::new(&["!"]);
+        prefixes.mentions.push("ByersBot".to_string());
+        assert_eq!(prefixes.strip("!"), None);
+        assert_eq!(prefixes.strip("  !  "), None);
+        assert_eq!(prefixes.strip("@ByersBot"), None);
+        assert_eq!(prefixes.strip("@ByersBot   "), None);
+        assert_eq!(prefixes.strip(""), None);
+        assert_eq!(CommandPrefixes::new(&[""]).strip("give"), None);
+    }
+
+    #[test]
+    fn prefixes_are_case_sensitive_and_mentions_are_not() {
+        let mut prefixes = CommandPrefixes::new(&["bot:"]);
+        prefixes.mentions.push("ByersBot".to_string());
+        assert_eq!(prefixes.strip("bot:give"), Some(("bot:", "give")));
+        assert_eq!(prefixes.strip("BOT:give"), None);
+        assert_eq!(prefixes.strip("@byersbot  give 10"), Some(("@byersbot", "give 10")));
+        assert_eq!(prefixes.strip("@ByersBotgive"), None);
+        assert_eq!(prefixes.strip("@Byers give"), None);
+        assert_eq!(prefixes.strip("@ByersBöt give"), None);
+    }
+
+    #[test]
+    fn compact_diagnostics_mark_the_offending_text() {
+        let text = "!say \"hello world";
+        let unclosed = StringViewError::ExpectedClosingQuote { close_quote: "\"".to_string(), span: Span::new(5, text.len()) };
+        assert_eq!(unclosed.render_compact(text), "Expected closing quote '\"': !say >>\"hello world<<");
+
+        let text = "!say a\"b";
+        let stray = StringViewError::UnexpectedQuote { quote: "\"".to_string(), span: Span::new(6, 7) };
+        assert_eq!(stray.render_compact(text), "Unexpected quote '\"' in non-quoted string: !say a>>\"<<b");
+    }
 
-        Ok(Some(result.into_iter().collect()))
+    #[test]
+    fn compact_diagnostics_shorten_long_context() {
+        let text = format!("!say {} a\"b {}", "x".repeat(20), "y".repeat(20));
+        let start = text.find('"').unwrap();
+        let stray = StringViewError::UnexpectedQuote { quote: "\"".to_string(), span: Span::new(start, start + 1) };
+        assert_eq!(
+            stray.render_compact(&text),
+            format!("Unexpected quote '\"' in non-quoted string: …{} a>>\"<<b {}…", "x".repeat(13), "y".repeat(13))
+        );
+
+        let text = format!("\"{}", "z".repeat(40));
+        let unclosed = StringViewError::ExpectedClosingQuote { close_quote: "\"".to_string(), span: Span::new(0, text.len()) };
+        assert_eq!(unclosed.render_compact(&text), format!("Expected closing quote '\"': >>\"{}…<<", "z".repeat(29)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn verbose_diagnostics_underline_the_offending_text() {
+        let text = "!say \"hello world";
+        let unclosed = StringViewError::ExpectedClosingQuote { close_quote: "\"".to_string(), span: Span::new(5, text.len()) };
+        assert_eq!(unclosed.render_verbose(text), "Expected closing quote '\"' at column 6\n  | !say \"hello world\n  |      ^^^^^^^^^^^^");
+
+        let text = "!say a\"b";
+        let stray = StringViewError::UnexpectedQuote { quote: "\"".to_string(), span: Span::new(6, 7) };
+        assert_eq!(stray.render_verbose(text), "Unexpected quote '\"' in non-quoted string at column 7\n  | !say a\"b\n  |       ^");
+    }
+
+    #[test]
+    fn verbose_diagnostics_count_characters() {
+        let text = "ä «ö";
+        let unclosed = StringViewError::ExpectedClosingQuote { close_quote: "»".to_string(), span: Span::new(3, text.len()) };
+        assert_eq!(unclosed.render_verbose(text), "Expected closing quote '»' at column 3\n  | ä «ö\n  |   ^^");
+
+        // empty spans are still marked
+        let text = "\"a\"b";
+        let invalid_end = StringViewError::InvalidEndOfQuotedString { char: "b".to_string(), span: Span::new(3, 3) };
+        assert_eq!(invalid_end.render_verbose(text), "Expected space after closing quotation but received b at column 4\n  | \"a\"b\n  |    ^");
+    }
+
+    /// The number of characters the parser moves past while splitting `text` leniently.
+    fn lenient_parse_steps(text: &str) -> usize {
+        let mut view = StringView::with_config(text.to_string(), ParserConfig::lenient());
+        view.get_parameters().unwrap();
+        view.visited
+    }
+
+    proptest! {
+        #[test]
+        fn lenient_parsing_is_linear(pattern in "[\"«»“” a-z]{1,8}[\"«“] ", repeat in 1usize..200) {
+            // every kind of opening quote is scanned past at most once before it is taken literally,
+            // so the three kinds in the pattern add at most three passes to reading each word once more
+            let text = pattern.repeat(repeat);
+            let steps = lenient_parse_steps(&text);
+            prop_assert!(steps <= 5 * text.chars().count(), "{:?} took {} steps for {} characters", text, steps, text.chars().count());
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn quoted_words_round_trip(pair in proptest::sample::select(quote_pairs()), content in "[^\\\\]*") {
+            let (open, close) = pair;
+            let text = format!("{} after", quote(&content, open, close));
+            let mut view = StringView::new(text);
+            prop_assert_eq!(view.get_parameters().unwrap(), vec![content, "after".to_string()]);
+        }
+
+        #[test]
+        fn arbitrary_text_never_panics(text in any::<String>()) {
+            check_boundaries(&text, ParserConfig::default());
+            check_boundaries(&text, ParserConfig::lenient());
+        }
+
+        #[test]
+        fn quote_heavy_text_never_panics(text in "[\"“”«»「」 \\\\a-zé🎲]{0,40}") {
+            check_boundaries(&text, ParserConfig::default());
+            check_boundaries(&text, ParserConfig::lenient());
+        }
+    }
+}
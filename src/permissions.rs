@@ -0,0 +1,397 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use crate::CommandError;
+use crate::ranks::RankHierarchy;
+use crate::structs::CommandUser;
+
+/// What a user needs in order to run a command.
+///
+/// Every listed permission is required. If ranks are listed, the user needs to have one of them,
+/// and if groups are listed, the user needs to be in one of them, so listing both requires both.
+/// A minimum rank has to be met in addition to that.
+#[derive(Clone, Debug, Default)]
+pub struct Requirements {
+    /// Permissions the user needs to hold, either directly or through one of their groups
+    pub permissions: Vec<String>,
+    /// Ranks of which the user needs to have one
+    pub ranks: Vec<String>,
+    /// Groups of which the user needs to be in one
+    pub groups: Vec<String>,
+    /// The lowest rank that is allowed to run the command, according to the host's [`RankHierarchy`]
+    pub min_rank: Option<String>,
+}
+
+impl Requirements {
+    pub fn is_empty(&self) -> bool {
+        self.permissions.is_empty() && self.ranks.is_empty() && self.groups.is_empty() && self.min_rank.is_none()
+    }
+
+    /// Checks whether `user` meets these requirements, resolving permissions without any group configuration.
+    ///
+    /// As there is no rank hierarchy, a minimum rank is never met.
+    pub fn check(&self, user: &CommandUser) -> Result<(), CommandError> {
+        self.check_with(user, &PermissionResolver::default(), &RankHierarchy::default())
+    }
+
+    /// Checks whether `user` meets these requirements, resolving permissions with `resolver` and comparing ranks with `hierarchy`.
+    pub fn check_with(&self, user: &CommandUser, resolver: &PermissionResolver, hierarchy: &RankHierarchy) -> Result<(), CommandError> {
+        if let Some(min_rank) = self.min_rank.as_ref() {
+            if !user.rank_at_least(min_rank, hierarchy) {
+                return Err(CommandError::MissingRank { ranks: format!("{} or higher", min_rank) });
+            }
+        }
+        if !self.ranks.is_empty() && !self.ranks.iter().any(|rank| rank.eq_ignore_ascii_case(&user.rank)) {
+            return Err(CommandError::MissingRank { ranks: self.ranks.join(", ") });
+        }
+        if !self.groups.is_empty() && !self.groups.iter().any(|group| user.in_group(group)) {
+            return Err(CommandError::MissingGroup { groups: self.groups.join(", ") });
+        }
+        for permission in self.permissions.iter() {
+            if !resolver.has_permission(user, permission) {
+                return Err(CommandError::MissingPermission { permission: permission.clone() });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A permission node as it is granted to a user or group, such as `economy.give`.
+///
+/// A node ending in `*` grants everything below it (`economy.*` grants `economy` and `economy.give`),
+/// `*` alone grants everything. A leading `-` turns the node into an explicit negation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionNode {
+    /// The segments of the node, without a trailing wildcard
+    segments: Vec<String>,
+    wildcard: bool,
+    pub negated: bool,
+}
+
+impl PermissionNode {
+    pub fn parse(node: &str) -> Self {
+        let node = node.trim();
+        let (negated, node) = match node.strip_prefix('-') {
+            Some(node) => (true, node),
+            None => (false, node),
+        };
+        let (wildcard, node) = if node == "*" {
+            (true, "")
+        } else {
+            match node.strip_suffix(".*") {
+                Some(node) => (true, node),
+                None => (false, node),
+            }
+        };
+
+        PermissionNode {
+            segments: node.split('.').filter(|segment| !segment.is_empty()).map(|segment| segment.to_lowercase()).collect(),
+            wildcard,
+            negated,
+        }
+    }
+
+    /// How specifically this node matches `permission`, or `None` if it does not match.
+    ///
+    /// Longer nodes are more specific, and an exact node is more specific than a wildcard of the same length.
+    fn specificity(&self, permission: &[String]) -> Option<(usize, bool)> {
+        let matches = if self.wildcard {
+            permission.starts_with(&self.segments)
+        } else {
+            permission == self.segments.as_slice()
+        };
+        if matches {
+            Some((self.segments.len(), !self.wildcard))
+        } else {
+            None
+        }
+    }
+}
+
+/// How a group behaves during permission resolution, as configured by the host.
+#[derive(Debug, Clone, Default)]
+pub struct GroupConfig {
+    /// Groups with a higher priority take precedence over groups with a lower one
+    pub priority: i32,
+    /// Groups whose permissions this group inherits, which take precedence after the group's own permissions
+    pub inherits: Vec<String>,
+    /// Permissions granted in addition to the ones the userservice reports for the group
+    pub permissions: Vec<String>,
+}
+
+/// Decides whether a user holds a permission.
+///
+/// Permissions are taken from a list of sources, in order of precedence: the user's direct permissions,
+/// then every group of the user by descending priority, each followed by the groups it inherits from.
+/// The first source with a node matching the permission decides. Within a source, the most specific
+/// node decides, and a negation beats a grant that is equally specific.
+#[derive(Debug, Clone, Default)]
+pub struct PermissionResolver {
+    /// Configuration of groups, keyed by their lowercase name
+    groups: HashMap<String, GroupConfig>,
+}
+
+impl PermissionResolver {
+    pub fn new() -> Self {
+        PermissionResolver::default()
+    }
+
+    /// Configures the group named `name`, replacing any previous configuration.
+    pub fn set_group(&mut self, name: &str, config: GroupConfig) {
+        self.groups.insert(name.to_lowercase(), config);
+    }
+
+    pub fn group(&self, name: &str) -> Option<&GroupConfig> {
+        self.groups.get(&name.to_lowercase())
+    }
+
+    /// Whether `user` holds `permission`.
+    pub fn has_permission(&self, user: &CommandUser, permission: &str) -> bool {
+        self.resolve(user, permission).unwrap_or(false)
+    }
+
+    /// Whether `user` is granted (`Some(true)`) or denied (`Some(false)`) `permission`, or `None`
+    /// if no node matches it.
+    pub fn resolve(&self, user: &CommandUser, permission: &str) -> Option<bool> {
+        let permission: Vec<String> = permission.split('.').filter(|segment| !segment.is_empty()).map(|segment| segment.to_lowercase()).collect();
+        self.sources(user).iter().find_map(|nodes| PermissionResolver::decide(nodes, &permission))
+    }
+
+    /// The most specific decision of `nodes` about `permission`.
+    fn decide(nodes: &[PermissionNode], permission: &[String]) -> Option<bool> {
+        let mut best: Option<((usize, bool), bool)> = None;
+        for node in nodes.iter() {
+            let specificity = match node.specificity(permission) {
+                Some(specificity) => specificity,
+                None => continue,
+            };
+            let replaces = match best {
+                None => true,
+                Some((best_specificity, granted)) => specificity > best_specificity || (specificity == best_specificity && granted && node.negated),
+            };
+            if replaces {
+                best = Some((specificity, !node.negated));
+            }
+        }
+        best.map(|(_, granted)| granted)
+    }
+
+    /// The nodes of every source the user gets permissions from, in order of precedence.
+    fn sources(&self, user: &CommandUser) -> Vec<Vec<PermissionNode>> {
+        let mut sources = vec![user.permissions.iter().map(|permission| PermissionNode::parse(&permission.name)).collect()];
+
+        let mut groups: Vec<&str> = user.groups.iter().map(|group| group.name.as_str()).collect();
+        // stable, so groups of the same priority keep the order reported by the userservice
+        groups.sort_by_key(|group| Reverse(self.group(group).map_or(0, |config| config.priority)));
+
+        let mut visited: HashSet<String> = HashSet::new();
+        for group in groups {
+            self.add_group_sources(user, group, &mut visited, &mut sources);
+        }
+        sources
+    }
+
+    fn add_group_sources(&self, user: &CommandUser, group: &str, visited: &mut HashSet<String>, sources: &mut Vec<Vec<PermissionNode>>) {
+        if !visited.insert(group.to_lowercase()) {
+            return;
+        }
+
+        let mut nodes: Vec<PermissionNode> = user.groups.iter()
+            .filter(|bpp_group| bpp_group.name.eq_ignore_ascii_case(group))
+            .flat_map(|bpp_group| bpp_group.permissions.iter())
+            .map(|permission| PermissionNode::parse(&permission.name))
+            .collect();
+        let config = self.group(group);
+        if let Some(config) = config {
+            nodes.extend(config.permissions.iter().map(|permission| PermissionNode::parse(permission)));
+        }
+        sources.push(nodes);
+
+        if let Some(config) = config {
+            for parent in config.inherits.iter() {
+                self.add_group_sources(user, parent, visited, sources);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::userservice::{BppGroup, Permission};
+
+    fn permissions(names: &[&str]) -> Vec<Permission> {
+        names.iter().map(|name| Permission { name: name.to_string() }).collect()
+    }
+
+    fn group(name: &str, names: &[&str]) -> BppGroup {
+        BppGroup { name: name.to_string(), permissions: permissions(names) }
+    }
+
+    fn user(groups: Vec<BppGroup>, names: &[&str]) -> CommandUser {
+        CommandUser {
+            groups,
+            permissions: permissions(names),
+            ..CommandUser::test_user("alice")
+        }
+    }
+
+    fn config(priority: i32, inherits: &[&str], permissions: &[&str]) -> GroupConfig {
+        GroupConfig {
+            priority,
+            inherits: inherits.iter().map(|group| group.to_string()).collect(),
+            permissions: permissions.iter().map(|permission| permission.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn star_grants_everything() {
+        let resolver = PermissionResolver::new();
+        let admin = user(Vec::new(), &["*"]);
+        assert!(resolver.has_permission(&admin, "economy.give"));
+        assert!(resolver.has_permission(&admin, "raffle"));
+    }
+
+    #[test]
+    fn wildcards_grant_the_node_and_everything_below_it() {
+        let resolver = PermissionResolver::new();
+        let banker = user(Vec::new(), &["economy.*"]);
+        assert!(resolver.has_permission(&banker, "economy"));
+        assert!(resolver.has_permission(&banker, "economy.give"));
+        assert!(resolver.has_permission(&banker, "Economy.Give.Max"));
+        assert!(!resolver.has_permission(&banker, "economyx"));
+        assert!(!resolver.has_permission(&banker, "raffle"));
+    }
+
+    #[test]
+    fn exact_nodes_do_not_grant_children() {
+        let resolver = PermissionResolver::new();
+        let giver = user(Vec::new(), &["economy.give"]);
+        assert!(resolver.has_permission(&giver, "economy.give"));
+        assert!(!resolver.has_permission(&giver, "economy"));
+        assert!(!resolver.has_permission(&giver, "economy.give.max"));
+    }
+
+    #[test]
+    fn negations_beat_equally_specific_grants() {
+        let resolver = PermissionResolver::new();
+        let denied = user(Vec::new(), &["economy.give", "-economy.give"]);
+        assert_eq!(resolver.resolve(&denied, "economy.give"), Some(false));
+        let denied = user(Vec::new(), &["-economy.*", "economy.*"]);
+        assert_eq!(resolver.resolve(&denied, "economy.give"), Some(false));
+    }
+
+    #[test]
+    fn more_specific_nodes_beat_wildcards() {
+        let resolver = PermissionResolver::new();
+        let user = user(Vec::new(), &["economy.*", "-economy.give", "-raffle.*", "raffle.join"]);
+        assert!(resolver.has_permission(&user, "economy.balance"));
+        assert!(!resolver.has_permission(&user, "economy.give"));
+        assert!(resolver.has_permission(&user, "raffle.join"));
+        assert!(!resolver.has_permission(&user, "raffle.draw"));
+    }
+
+    #[test]
+    fn unmatched_permissions_are_undecided() {
+        let resolver = PermissionResolver::new();
+        let user = user(Vec::new(), &["economy.give"]);
+        assert_eq!(resolver.resolve(&user, "raffle"), None);
+        assert!(!resolver.has_permission(&user, "raffle"));
+    }
+
+    #[test]
+    fn groups_with_a_higher_priority_decide_first() {
+        let mut resolver = PermissionResolver::new();
+        resolver.set_group("moderators", config(10, &[], &[]));
+        resolver.set_group("muted", config(20, &[], &[]));
+        let user = user(vec![group("moderators", &["chat.*"]), group("muted", &["-chat.send"])], &[]);
+        assert!(!resolver.has_permission(&user, "chat.send"));
+        assert!(resolver.has_permission(&user, "chat.delete"));
+
+        resolver.set_group("muted", config(0, &[], &[]));
+        assert!(resolver.has_permission(&user, "chat.send"));
+    }
+
+    #[test]
+    fn extreme_priorities_are_ordered() {
+        let mut resolver = PermissionResolver::new();
+        resolver.set_group("lowest", config(i32::MIN, &[], &["-raffle"]));
+        resolver.set_group("highest", config(i32::MAX, &[], &["raffle"]));
+        let user = user(vec![group("lowest", &[]), group("highest", &[])], &[]);
+        assert!(resolver.has_permission(&user, "raffle"));
+    }
+
+    #[test]
+    fn inherited_groups_decide_after_the_group_itself() {
+        let mut resolver = PermissionResolver::new();
+        resolver.set_group("moderators", config(0, &["viewers"], &["-raffle.join"]));
+        resolver.set_group("viewers", config(0, &[], &["raffle.*", "points"]));
+        let moderator = user(vec![group("Moderators", &["raffle.draw"])], &[]);
+        assert!(resolver.has_permission(&moderator, "raffle.draw"));
+        assert!(!resolver.has_permission(&moderator, "raffle.join"));
+        assert!(resolver.has_permission(&moderator, "raffle.leave"));
+        assert!(resolver.has_permission(&moderator, "points"));
+    }
+
+    #[test]
+    fn inheritance_cycles_terminate() {
+        let mut resolver = PermissionResolver::new();
+        resolver.set_group("a", config(0, &["b"], &[]));
+        resolver.set_group("b", config(0, &["c", "a"], &[]));
+        resolver.set_group("c", config(0, &["a", "b"], &["points"]));
+        let user = user(vec![group("a", &[])], &[]);
+        assert!(resolver.has_permission(&user, "points"));
+        assert!(!resolver.has_permission(&user, "raffle"));
+    }
+
+    #[test]
+    fn direct_permissions_beat_group_permissions() {
+        let mut resolver = PermissionResolver::new();
+        resolver.set_group("admins", config(100, &[], &["*"]));
+        let user = user(vec![group("admins", &[]), group("viewers", &["raffle.join"])], &["-economy.give", "-raffle.*"]);
+        assert!(!resolver.has_permission(&user, "economy.give"));
+        assert!(!resolver.has_permission(&user, "raffle.join"));
+        assert!(resolver.has_permission(&user, "economy.balance"));
+    }
+
+    #[test]
+    fn listed_ranks_and_groups_are_both_required() {
+        let requirements = Requirements {
+            ranks: vec!["Moderator".to_string(), "Owner".to_string()],
+            groups: vec!["staff".to_string()],
+            ..Requirements::default()
+        };
+        let resolver = PermissionResolver::new();
+        let hierarchy = RankHierarchy::default();
+        let with_rank = |rank: &str, groups: Vec<BppGroup>| CommandUser { rank: rank.to_string(), ..user(groups, &[]) };
+
+        assert!(requirements.check_with(&with_rank("moderator", vec![group("Staff", &[])]), &resolver, &hierarchy).is_ok());
+        assert!(requirements.check_with(&with_rank("Owner", vec![group("viewers", &[]), group("staff", &[])]), &resolver, &hierarchy).is_ok());
+        assert!(matches!(
+            requirements.check_with(&with_rank("Moderator", vec![group("viewers", &[])]), &resolver, &hierarchy),
+            Err(CommandError::MissingGroup { groups }) if groups == "staff"
+        ));
+        assert!(matches!(
+            requirements.check_with(&with_rank("Viewer", vec![group("staff", &[])]), &resolver, &hierarchy),
+            Err(CommandError::MissingRank { ranks }) if ranks == "Moderator, Owner"
+        ));
+    }
+
+    #[test]
+    fn the_minimum_rank_is_checked_against_the_hierarchy() {
+        let requirements = Requirements { min_rank: Some("moderator".to_string()), ..Requirements::default() };
+        let unknown_rank = Requirements { min_rank: Some("admin".to_string()), ..Requirements::default() };
+        let resolver = PermissionResolver::new();
+        let hierarchy: RankHierarchy = "viewer < moderator < owner".parse().unwrap();
+        let with_rank = |rank: &str| CommandUser { rank: rank.to_string(), ..user(vec![], &[]) };
+
+        assert!(requirements.check_with(&with_rank("Owner"), &resolver, &hierarchy).is_ok());
+        assert!(requirements.check_with(&with_rank("moderator"), &resolver, &hierarchy).is_ok());
+        assert!(matches!(
+            requirements.check_with(&with_rank("viewer"), &resolver, &hierarchy),
+            Err(CommandError::MissingRank { ranks }) if ranks == "moderator or higher"
+        ));
+        assert!(matches!(unknown_rank.check_with(&with_rank("owner"), &resolver, &hierarchy), Err(CommandError::MissingRank { .. })));
+        assert!(requirements.check(&with_rank("owner")).is_err());
+    }
+}
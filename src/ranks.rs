@@ -0,0 +1,115 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+use custom_error::custom_error;
+
+custom_error! { pub RankError
+    EmptyRank = "Rank names must not be empty",
+    DuplicateRank{rank: String} = "The rank '{rank}' is listed more than once"
+}
+
+/// An ordered list of ranks, from lowest to highest.
+///
+/// Rank names are compared case-insensitively. Ranks that are not part of the hierarchy are below
+/// every rank, and are not at least any rank.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RankHierarchy {
+    ranks: Vec<String>,
+}
+
+impl RankHierarchy {
+    /// Creates a hierarchy from ranks ordered from lowest to highest.
+    pub fn new(ranks: &[&str]) -> Result<Self, RankError> {
+        let mut hierarchy = RankHierarchy::default();
+        for rank in ranks {
+            let rank = rank.trim();
+            if rank.is_empty() {
+                return Err(RankError::EmptyRank);
+            }
+            if hierarchy.position(rank).is_some() {
+                return Err(RankError::DuplicateRank { rank: rank.to_string() });
+            }
+            hierarchy.ranks.push(rank.to_string());
+        }
+        Ok(hierarchy)
+    }
+
+    /// The ranks, from lowest to highest.
+    pub fn ranks(&self) -> &[String] {
+        &self.ranks
+    }
+
+    /// The position of `rank` in the hierarchy, where 0 is the lowest rank.
+    pub fn position(&self, rank: &str) -> Option<usize> {
+        self.ranks.iter().position(|known| known.eq_ignore_ascii_case(rank.trim()))
+    }
+
+    /// Compares two ranks, or returns `None` if one of them is not part of the hierarchy.
+    pub fn compare(&self, rank: &str, other: &str) -> Option<Ordering> {
+        Some(self.position(rank)?.cmp(&self.position(other)?))
+    }
+
+    /// Whether `rank` is `minimum` or a higher rank.
+    pub fn is_at_least(&self, rank: &str, minimum: &str) -> bool {
+        matches!(self.compare(rank, minimum), Some(Ordering::Greater) | Some(Ordering::Equal))
+    }
+}
+
+impl FromStr for RankHierarchy {
+    type Err = RankError;
+
+    /// Parses a hierarchy from ranks separated by `<` or `,`, from lowest to highest,
+    /// e.g. `viewer < member < moderator < owner`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ranks: Vec<&str> = s.split(&['<', ','][..]).collect();
+        RankHierarchy::new(&ranks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hierarchy() -> RankHierarchy {
+        "viewer < member < Moderator < owner".parse().unwrap()
+    }
+
+    #[test]
+    fn hierarchies_are_parsed_from_lowest_to_highest() {
+        assert_eq!(hierarchy().ranks(), &["viewer", "member", "Moderator", "owner"]);
+        assert_eq!("viewer, member,moderator".parse::<RankHierarchy>().unwrap().ranks(), &["viewer", "member", "moderator"]);
+        assert_eq!("viewer, member < owner".parse::<RankHierarchy>().unwrap(), RankHierarchy::new(&["viewer", "member", "owner"]).unwrap());
+        assert!(matches!("viewer < < owner".parse::<RankHierarchy>(), Err(RankError::EmptyRank)));
+        assert!(matches!("".parse::<RankHierarchy>(), Err(RankError::EmptyRank)));
+    }
+
+    #[test]
+    fn duplicate_ranks_are_rejected() {
+        let error = "viewer < Member < member".parse::<RankHierarchy>().unwrap_err();
+        assert!(matches!(&error, RankError::DuplicateRank { rank } if rank == "member"));
+        assert_eq!(error.to_string(), "The rank 'member' is listed more than once");
+    }
+
+    #[test]
+    fn ranks_are_ordered_case_insensitively() {
+        let hierarchy = hierarchy();
+        assert_eq!(hierarchy.position("VIEWER"), Some(0));
+        assert_eq!(hierarchy.position(" moderator "), Some(2));
+        assert_eq!(hierarchy.compare("owner", "moderator"), Some(Ordering::Greater));
+        assert_eq!(hierarchy.compare("Member", "member"), Some(Ordering::Equal));
+        assert_eq!(hierarchy.compare("viewer", "member"), Some(Ordering::Less));
+        assert!(hierarchy.is_at_least("owner", "Moderator"));
+        assert!(hierarchy.is_at_least("moderator", "moderator"));
+        assert!(!hierarchy.is_at_least("member", "moderator"));
+    }
+
+    #[test]
+    fn unknown_ranks_are_never_at_least_any_rank() {
+        let hierarchy = hierarchy();
+        assert_eq!(hierarchy.position("guest"), None);
+        assert_eq!(hierarchy.compare("guest", "viewer"), None);
+        assert!(!hierarchy.is_at_least("guest", "viewer"));
+        // an unknown minimum rank can't be met either
+        assert!(!hierarchy.is_at_least("owner", "admin"));
+        assert!(!RankHierarchy::default().is_at_least("owner", "owner"));
+    }
+}
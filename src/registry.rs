@@ -0,0 +1,610 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use chrono::Utc;
+use custom_error::custom_error;
+use log::{error, warn};
+
+use crate::CommandError;
+use crate::context::{next_invocation_id, CommandContext, PluginConfig};
+use crate::cooldown::CooldownTracker;
+use crate::message::{CommandPrefixes, ParserConfig};
+use crate::permissions::PermissionResolver;
+use crate::ranks::RankHierarchy;
+use crate::structs::{CommandGroup, CommandMeta, CommandUser, Message, MissingTimestampPolicy, ServiceDirectory};
+use crate::userservice::BppUser;
+use crate::traits::{Command, CommandRegistrar};
+
+custom_error! { #[derive(Clone)] pub RegistryError
+    NameTaken{name: String} = "A command with the name '{name}' is already registered",
+    AliasTaken{alias: String, command: String} = "The alias '{alias}' is already used by the command '{command}'",
+    UnknownCommand{name: String} = "No command with the name or alias '{name}' is registered"
+}
+
+/// Normalises a command name or alias, so every lookup compares names the same way.
+fn normalize(name: &str) -> String {
+    name.to_lowercase()
+}
+
+/// A command together with the names it can be invoked with.
+#[derive(Clone)]
+pub struct RegisteredCommand {
+    /// The primary name of the command
+    pub name: String,
+    /// Additional names the command can be invoked with
+    pub aliases: Vec<String>,
+    /// The command handler, `None` for groups that only consist of subcommands
+    pub command: Option<Box<dyn Command>>,
+    /// Information provided when the command was registered
+    pub meta: CommandMeta,
+    pub subcommands: Vec<RegisteredCommand>,
+}
+
+impl RegisteredCommand {
+    /// Converts a group, rejecting subcommands whose names collide with their siblings.
+    fn from_group(group: CommandGroup) -> Result<Self, RegistryError> {
+        let mut subcommands: Vec<RegisteredCommand> = Vec::new();
+        for subcommand in group.subcommands {
+            for name in std::iter::once(&subcommand.name).chain(subcommand.aliases.iter()) {
+                if let Some(sibling) = subcommands.iter().find(|sibling| sibling.matches(name)) {
+                    return Err(RegistryError::AliasTaken {
+                        alias: name.clone(),
+                        command: format!("{} {}", group.name, sibling.name),
+                    });
+                }
+            }
+            subcommands.push(RegisteredCommand::from_group(subcommand)?);
+        }
+
+        Ok(RegisteredCommand {
+            name: group.name,
+            aliases: group.aliases,
+            command: group.command,
+            meta: group.meta,
+            subcommands,
+        })
+    }
+
+    /// Converts the command back into a group, e.g. for registering it elsewhere.
+    pub fn to_group(&self) -> CommandGroup {
+        CommandGroup {
+            name: self.name.clone(),
+            aliases: self.aliases.clone(),
+            command: self.command.clone(),
+            meta: self.meta.clone(),
+            subcommands: self.subcommands.iter().map(|subcommand| subcommand.to_group()).collect(),
+        }
+    }
+
+    /// Checks whether `name` is the name or one of the aliases of this command, ignoring case.
+    pub fn matches(&self, name: &str) -> bool {
+        let name = normalize(name);
+        normalize(&self.name) == name || self.aliases.iter().any(|alias| normalize(alias) == name)
+    }
+
+    /// Finds a direct subcommand by its name or one of its aliases.
+    pub fn subcommand(&self, name: &str) -> Option<&RegisteredCommand> {
+        self.subcommands.iter().find(|subcommand| subcommand.matches(name))
+    }
+}
+
+/// A reference implementation of [`CommandRegistrar`].
+///
+/// Names and aliases are matched case-insensitively. Registering a command whose name or aliases
+/// collide with an already registered command is rejected as a whole.
+///
+/// Clones share their cooldowns, so replacing a registry with an updated clone keeps them.
+#[derive(Clone, Default)]
+pub struct CommandRegistry {
+    /// Registered commands, keyed by their normalised primary name
+    commands: HashMap<String, RegisteredCommand>,
+    /// Maps every normalised name and alias to the key of its command
+    names: HashMap<String, String>,
+    /// Configuration of commands, keyed by their normalised primary name
+    configs: HashMap<String, Arc<PluginConfig>>,
+    /// Collisions that occurred while registering through [`CommandRegistrar`]
+    collisions: Vec<RegistryError>,
+    cooldowns: Arc<CooldownTracker>,
+    /// How user records without timestamps are handled by [`CommandRegistry::dispatch_chat`]
+    missing_timestamps: MissingTimestampPolicy,
+    /// Resolves the permissions of requirements and cooldown bypasses
+    permissions: PermissionResolver,
+    /// Orders ranks for minimum rank requirements
+    ranks: RankHierarchy,
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        CommandRegistry::default()
+    }
+
+    /// Registers a command, returning an error if the name or one of the aliases is already taken.
+    pub fn try_register(&mut self, name: &str, aliases: &[&str], command: Box<dyn Command>, meta: CommandMeta) -> Result<(), RegistryError> {
+        self.try_register_group(CommandGroup::new(name, aliases, Some(command), meta))
+    }
+
+    /// Registers a command with subcommands, returning an error if the name or one of the aliases
+    /// is already taken, or if subcommands of the same command share a name.
+    pub fn try_register_group(&mut self, group: CommandGroup) -> Result<(), RegistryError> {
+        let key = normalize(&group.name);
+        if let Some(owner) = self.names.get(&key) {
+            if owner == &key {
+                return Err(RegistryError::NameTaken { name: group.name });
+            }
+            return Err(RegistryError::AliasTaken {
+                alias: group.name,
+                command: self.commands[owner].name.clone(),
+            });
+        }
+
+        let mut alias_keys: Vec<String> = Vec::new();
+        for alias in group.aliases.iter() {
+            let alias_key = normalize(alias);
+            if alias_key == key || alias_keys.contains(&alias_key) {
+                continue;
+            }
+            if let Some(owner) = self.names.get(&alias_key) {
+                return Err(RegistryError::AliasTaken {
+                    alias: alias.to_string(),
+                    command: self.commands[owner].name.clone(),
+                });
+            }
+            alias_keys.push(alias_key);
+        }
+
+        let registered = RegisteredCommand::from_group(group)?;
+        for alias_key in alias_keys.iter() {
+            self.names.insert(alias_key.clone(), key.clone());
+        }
+        self.names.insert(key.clone(), key.clone());
+        self.commands.insert(key, registered);
+        Ok(())
+    }
+
+    /// Removes a command by its name or one of its aliases.
+    pub fn unregister(&mut self, name: &str) -> Result<RegisteredCommand, RegistryError> {
+        let key = self.names.get(&normalize(name)).cloned();
+        if let Some(key) = key {
+            self.names.retain(|_, owner| owner != &key);
+            self.configs.remove(&key);
+            self.cooldowns.reset(&key);
+            Ok(self.commands.remove(&key).unwrap())
+        } else {
+            Err(RegistryError::UnknownCommand { name: name.to_string() })
+        }
+    }
+
+    /// Sets the configuration that is passed to a command and its subcommands when they run.
+    pub fn configure(&mut self, name: &str, config: PluginConfig) -> Result<(), RegistryError> {
+        match self.names.get(&normalize(name)) {
+            Some(key) => {
+                self.configs.insert(key.clone(), Arc::new(config));
+                Ok(())
+            }
+            None => Err(RegistryError::UnknownCommand { name: name.to_string() }),
+        }
+    }
+
+    /// The configuration of a command, empty if none was set.
+    pub fn config(&self, name: &str) -> Option<Arc<PluginConfig>> {
+        self.names.get(&normalize(name))
+            .map(|key| self.configs.get(key).cloned().unwrap_or_default())
+    }
+
+    /// Finds a command by its name or one of its aliases.
+    pub fn lookup(&self, name: &str) -> Option<&RegisteredCommand> {
+        self.names.get(&normalize(name)).and_then(|key| self.commands.get(key))
+    }
+
+    /// Finds a command or subcommand by a path of names, such as `["bank", "deposit"]`.
+    pub fn lookup_path(&self, path: &[&str]) -> Option<&RegisteredCommand> {
+        let (first, rest) = path.split_first()?;
+        let mut registered = self.lookup(first)?;
+        for name in rest {
+            registered = registered.subcommand(name)?;
+        }
+        Some(registered)
+    }
+
+    /// Iterates over all registered top-level commands in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &RegisteredCommand> {
+        self.commands.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Collisions that were rejected while registering through [`CommandRegistrar`].
+    pub fn collisions(&self) -> &[RegistryError] {
+        &self.collisions
+    }
+
+    /// Takes the collisions that were reported so far, leaving an empty list behind.
+    pub fn take_collisions(&mut self) -> Vec<RegistryError> {
+        std::mem::take(&mut self.collisions)
+    }
+
+    /// Sets the group configuration used to resolve permissions when dispatching.
+    pub fn set_permission_resolver(&mut self, resolver: PermissionResolver) {
+        self.permissions = resolver;
+    }
+
+    pub fn permission_resolver(&self) -> &PermissionResolver {
+        &self.permissions
+    }
+
+    /// Sets the hierarchy used to enforce minimum rank requirements when dispatching.
+    pub fn set_rank_hierarchy(&mut self, hierarchy: RankHierarchy) {
+        self.ranks = hierarchy;
+    }
+
+    pub fn rank_hierarchy(&self) -> &RankHierarchy {
+        &self.ranks
+    }
+
+    pub fn set_missing_timestamp_policy(&mut self, policy: MissingTimestampPolicy) {
+        self.missing_timestamps = policy;
+    }
+
+    /// Parses a chat message from a user record and executes the command it names, see [`CommandRegistry::dispatch`].
+    ///
+    /// A malformed user record fails with [`CommandError::InvalidUser`] and is logged, instead of
+    /// being converted with `unwrap`.
+    pub async fn dispatch_chat(&self, user: BppUser, text: String, prefixes: &CommandPrefixes, parser_config: &ParserConfig, service_directory: &mut ServiceDirectory<'_>) -> Result<(), CommandError> {
+        let channel_id = user.channel_id.clone();
+        let user = match CommandUser::from_bpp_user(user, self.missing_timestamps) {
+            Ok(user) => user,
+            Err(source) => {
+                error!("Ignoring message from user '{}': {}", channel_id, source);
+                return Err(CommandError::InvalidUser { source });
+            }
+        };
+        self.dispatch(Message::with_config(user, text, prefixes, parser_config), service_directory).await
+    }
+
+    /// Executes the command named by `message.command_name`.
+    ///
+    /// Leading arguments are matched against subcommands for as long as possible, and the deepest
+    /// matching subcommand receives the remaining arguments. The requirements of the command and of
+    /// every subcommand on the way have to be met, and the cooldowns of the executed command are
+    /// checked before it runs.
+    ///
+    /// Messages that start with a prefix but cannot be parsed fail with [`CommandError::ParseFailure`],
+    /// replying with a diagnostic that points at the offending text. Other messages without command
+    /// information are ignored.
+    pub async fn dispatch(&self, mut message: Message, service_directory: &mut ServiceDirectory<'_>) -> Result<(), CommandError> {
+        if let Some(error) = message.parse_error.take() {
+            let reply = error.render_compact(&message.message);
+            return Err(CommandError::ParseFailure { source: error }.with_reply(reply));
+        }
+        if !message.has_command_info {
+            return Ok(());
+        }
+
+        let mut registered = match self.lookup(&message.command_name) {
+            Some(registered) => registered,
+            None => return Err(CommandError::UnknownCommand { name: message.command_name }),
+        };
+        registered.meta.requirements.check_with(&message.user, &self.permissions, &self.ranks)?;
+        let config = self.config(&registered.name).unwrap_or_default();
+
+        let mut path = vec![registered.name.clone()];
+        let mut depth = 0;
+        while let Some(subcommand) = message.command_args.get(depth).and_then(|arg| registered.subcommand(arg)) {
+            subcommand.meta.requirements.check_with(&message.user, &self.permissions, &self.ranks)?;
+            path.push(subcommand.name.clone());
+            registered = subcommand;
+            depth += 1;
+        }
+        message.command_args.drain(..depth);
+        message.command_args_quoted.drain(..depth.min(message.command_args_quoted.len()));
+
+        let command = match registered.command.as_ref() {
+            Some(command) => command,
+            None => {
+                let subcommands: Vec<&str> = registered.subcommands.iter()
+                    .filter(|subcommand| !subcommand.meta.hidden)
+                    .map(|subcommand| subcommand.name.as_str())
+                    .collect();
+                return Err(CommandError::user(format!("Usage: {}{} <{}>", message.prefix, path.join(" "), subcommands.join("|"))));
+            }
+        };
+        self.cooldowns.check(&normalize(&path.join(" ")), &registered.meta.cooldowns, &message.user, &self.permissions)?;
+
+        let context = CommandContext {
+            alias: message.command_name.clone(),
+            prefix: message.prefix.clone(),
+            path,
+            reply: service_directory.reply_to(&message),
+            registry: self,
+            config,
+            invoked_at: Utc::now(),
+            invocation_id: next_invocation_id(),
+            message,
+            services: service_directory,
+        };
+        command.run(context).await
+    }
+}
+
+/// A registry that can be updated while its commands are running.
+///
+/// Commands are dispatched on a [`snapshot`](SharedRegistry::snapshot) of the registry, so no lock is
+/// held while they execute, and [`update`](SharedRegistry::update) replaces the registry with an
+/// updated copy. Executions that already started keep the snapshot they were dispatched with, which
+/// means updating never waits for a command, and a command can update the registry it runs in.
+#[derive(Default)]
+pub struct SharedRegistry {
+    current: Mutex<Arc<CommandRegistry>>,
+}
+
+impl SharedRegistry {
+    pub fn new(registry: CommandRegistry) -> Self {
+        SharedRegistry {
+            current: Mutex::new(Arc::new(registry)),
+        }
+    }
+
+    /// The current registry, which is not affected by later updates.
+    pub fn snapshot(&self) -> Arc<CommandRegistry> {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Applies `update` to a copy of the current registry, which then replaces the current registry.
+    ///
+    /// Updates run one at a time, so concurrent updates are never lost.
+    pub fn update<R, F: FnOnce(&mut CommandRegistry) -> R>(&self, update: F) -> R {
+        let mut current = self.current.lock().unwrap();
+        let mut registry = CommandRegistry::clone(&current);
+        let result = update(&mut registry);
+        *current = Arc::new(registry);
+        result
+    }
+}
+
+impl CommandRegistrar for CommandRegistry {
+    fn register_command(&mut self, name: &str, aliases: &[&str], command: Box<dyn Command>) {
+        self.register_command_with_meta(name, aliases, command, CommandMeta::default());
+    }
+
+    fn register_command_with_meta(&mut self, name: &str, aliases: &[&str], command: Box<dyn Command>, meta: CommandMeta) -> bool {
+        self.register_group(CommandGroup::new(name, aliases, Some(command), meta))
+    }
+
+    fn register_group(&mut self, group: CommandGroup) -> bool {
+        let name = group.name.clone();
+        match self.try_register_group(group) {
+            Ok(_) => true,
+            Err(error) => {
+                warn!("Unable to register command '{}': {}", name, error);
+                self.collisions.push(error);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::userservice::user_service_client::UserServiceClient;
+    use crate::youtubeservice::you_tube_service_client::YouTubeServiceClient;
+
+    #[derive(Clone)]
+    struct Noop;
+
+    #[async_trait]
+    impl Command for Noop {
+        async fn run(&self, _context: CommandContext<'_, '_>) -> Result<(), CommandError> {
+            Ok(())
+        }
+    }
+
+    fn register(registry: &mut CommandRegistry, name: &str, aliases: &[&str]) -> Result<(), RegistryError> {
+        registry.try_register(name, aliases, Box::new(Noop), CommandMeta::default())
+    }
+
+    #[test]
+    fn names_and_aliases_are_matched_ignoring_case() {
+        let mut registry = CommandRegistry::new();
+        register(&mut registry, "Give", &["Transfer"]).unwrap();
+
+        assert_eq!(registry.lookup("give").unwrap().name, "Give");
+        assert_eq!(registry.lookup("TRANSFER").unwrap().name, "Give");
+        assert!(registry.lookup("giv").is_none());
+        assert!(registry.lookup("Give").unwrap().matches("gIvE"));
+        assert!(registry.lookup("Give").unwrap().matches("transfer"));
+    }
+
+    #[test]
+    fn non_ascii_names_are_matched_the_same_way_everywhere() {
+        let mut registry = CommandRegistry::new();
+        registry.try_register_group(CommandGroup::new("Ärger", &[], Some(Box::new(Noop)), CommandMeta::default())
+            .subcommand(CommandGroup::new("Übersicht", &[], Some(Box::new(Noop)), CommandMeta::default()))).unwrap();
+
+        assert!(registry.lookup("ärger").is_some());
+        assert!(registry.lookup("ÄRGER").unwrap().subcommand("übersicht").is_some());
+    }
+
+    #[test]
+    fn colliding_names_and_aliases_are_rejected() {
+        let mut registry = CommandRegistry::new();
+        register(&mut registry, "give", &["transfer"]).unwrap();
+
+        assert!(matches!(register(&mut registry, "GIVE", &[]), Err(RegistryError::NameTaken { name }) if name == "GIVE"));
+        assert!(matches!(register(&mut registry, "transfer", &[]), Err(RegistryError::AliasTaken { alias, command }) if alias == "transfer" && command == "give"));
+        assert!(matches!(register(&mut registry, "send", &["Give"]), Err(RegistryError::AliasTaken { alias, command }) if alias == "Give" && command == "give"));
+        assert!(matches!(register(&mut registry, "send", &["pay", "TRANSFER"]), Err(RegistryError::AliasTaken { .. })));
+
+        // a rejected command does not leave any of its names behind
+        assert!(registry.lookup("send").is_none());
+        assert!(registry.lookup("pay").is_none());
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn aliases_repeating_the_name_are_ignored() {
+        let mut registry = CommandRegistry::new();
+        register(&mut registry, "give", &["GIVE", "pay", "Pay"]).unwrap();
+
+        assert_eq!(registry.lookup("pay").unwrap().name, "give");
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn colliding_subcommands_are_rejected() {
+        let mut registry = CommandRegistry::new();
+        let group = CommandGroup::new("bank", &[], None, CommandMeta::default())
+            .subcommand(CommandGroup::new("deposit", &["put"], Some(Box::new(Noop)), CommandMeta::default()))
+            .subcommand(CommandGroup::new("PUT", &[], Some(Box::new(Noop)), CommandMeta::default()));
+
+        assert!(matches!(registry.try_register_group(group), Err(RegistryError::AliasTaken { alias, command }) if alias == "PUT" && command == "bank deposit"));
+        assert!(registry.is_empty());
+    }
+
+    #[test]
+    fn collisions_through_the_registrar_are_recorded() {
+        let mut registry = CommandRegistry::new();
+
+        assert!(registry.register_command_with_meta("give", &[], Box::new(Noop), CommandMeta::default()));
+        assert!(!registry.register_command_with_meta("Give", &[], Box::new(Noop), CommandMeta::default()));
+        assert_eq!(registry.collisions().len(), 1);
+        assert_eq!(registry.take_collisions().len(), 1);
+        assert!(registry.collisions().is_empty());
+    }
+
+    #[test]
+    fn unregister_removes_the_name_aliases_and_config() {
+        let mut registry = CommandRegistry::new();
+        register(&mut registry, "give", &["transfer", "pay"]).unwrap();
+        register(&mut registry, "points", &[]).unwrap();
+        registry.configure("give", PluginConfig::default()).unwrap();
+
+        let removed = registry.unregister("TRANSFER").unwrap();
+
+        assert_eq!(removed.name, "give");
+        assert!(registry.lookup("give").is_none());
+        assert!(registry.lookup("transfer").is_none());
+        assert!(registry.lookup("pay").is_none());
+        assert!(registry.config("give").is_none());
+        assert!(registry.lookup("points").is_some());
+        assert_eq!(registry.len(), 1);
+
+        // the names are free again
+        register(&mut registry, "pay", &["give"]).unwrap();
+    }
+
+    #[test]
+    fn unregistering_an_unknown_command_fails() {
+        let mut registry = CommandRegistry::new();
+
+        assert!(matches!(registry.unregister("give"), Err(RegistryError::UnknownCommand { name }) if name == "give"));
+    }
+
+    #[test]
+    fn shared_registry_updates_do_not_affect_snapshots() {
+        let registry = SharedRegistry::default();
+        let before = registry.snapshot();
+
+        registry.update(|registry| register(registry, "give", &[])).unwrap();
+
+        assert!(before.lookup("give").is_none());
+        assert!(registry.snapshot().lookup("give").is_some());
+    }
+
+    /// The path, arguments and whether each argument was quoted.
+    type Call = (Vec<String>, Vec<String>, Vec<bool>);
+
+    /// Records the path and arguments of every invocation.
+    #[derive(Clone, Default)]
+    struct Record {
+        calls: Arc<Mutex<Vec<Call>>>,
+    }
+
+    #[async_trait]
+    impl Command for Record {
+        async fn run(&self, context: CommandContext<'_, '_>) -> Result<(), CommandError> {
+            let message = context.message;
+            self.calls.lock().unwrap().push((context.path, message.command_args, message.command_args_quoted));
+            Ok(())
+        }
+    }
+
+    /// `bank` without a command of its own, with `deposit` (alias `put`), `deposit all` and a hidden `audit`.
+    fn bank(record: &Record) -> CommandGroup {
+        let recorded = || -> Option<Box<dyn Command>> { Some(Box::new(record.clone())) };
+        CommandGroup::new("bank", &[], None, CommandMeta::default())
+            .subcommand(CommandGroup::new("deposit", &["put"], recorded(), CommandMeta::default())
+                .subcommand(CommandGroup::new("all", &[], recorded(), CommandMeta::default())))
+            .subcommand(CommandGroup::new("audit", &[], recorded(), CommandMeta { hidden: true, ..CommandMeta::default() }))
+    }
+
+    async fn dispatch(registry: &CommandRegistry, text: &str) -> Result<(), CommandError> {
+        let channel = tonic::transport::Endpoint::from_static("http://[::1]:50051").connect_lazy().unwrap();
+        let mut userservice_client = UserServiceClient::new(channel.clone());
+        let mut youtubeservice_client = YouTubeServiceClient::new(channel);
+        let mut services = ServiceDirectory {
+            userservice_client: &mut userservice_client,
+            youtubeservice_client: &mut youtubeservice_client,
+            economy: None,
+        };
+        registry.dispatch(Message::new(CommandUser::test_user("alice"), text.to_string()), &mut services).await
+    }
+
+    #[test]
+    fn lookup_path_walks_subcommands() {
+        let mut registry = CommandRegistry::new();
+        registry.try_register_group(bank(&Record::default())).unwrap();
+
+        assert_eq!(registry.lookup_path(&["bank"]).unwrap().name, "bank");
+        assert_eq!(registry.lookup_path(&["BANK", "Put"]).unwrap().name, "deposit");
+        assert_eq!(registry.lookup_path(&["bank", "deposit", "all"]).unwrap().name, "all");
+        assert!(registry.lookup_path(&["bank", "withdraw"]).is_none());
+        assert!(registry.lookup_path(&["bank", "all"]).is_none());
+        assert!(registry.lookup_path(&["deposit"]).is_none());
+        assert!(registry.lookup_path(&[]).is_none());
+    }
+
+    #[tokio::test]
+    async fn dispatch_runs_the_deepest_subcommand_with_the_remaining_arguments() {
+        let record = Record::default();
+        let mut registry = CommandRegistry::new();
+        registry.try_register_group(bank(&record)).unwrap();
+
+        dispatch(&registry, "!bank PUT 10 \"all of it\"").await.unwrap();
+        dispatch(&registry, "!bank deposit all").await.unwrap();
+        // only leading arguments select subcommands
+        dispatch(&registry, "!bank deposit 10 all").await.unwrap();
+
+        let calls = record.calls.lock().unwrap();
+        assert_eq!(calls[0], (vec!["bank".to_string(), "deposit".to_string()], vec!["10".to_string(), "all of it".to_string()], vec![false, true]));
+        assert_eq!(calls[1], (vec!["bank".to_string(), "deposit".to_string(), "all".to_string()], Vec::new(), Vec::new()));
+        assert_eq!(calls[2], (vec!["bank".to_string(), "deposit".to_string()], vec!["10".to_string(), "all".to_string()], vec![false, false]));
+    }
+
+    #[tokio::test]
+    async fn dispatching_a_group_without_a_command_lists_its_subcommands() {
+        let record = Record::default();
+        let mut registry = CommandRegistry::new();
+        registry.try_register_group(bank(&record)).unwrap();
+
+        let error = dispatch(&registry, "!bank withdraw 10").await.unwrap_err();
+
+        assert_eq!(error.to_string(), "Usage: !bank <deposit>");
+        assert!(record.calls.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn dispatching_an_unknown_command_fails() {
+        let registry = CommandRegistry::new();
+
+        assert!(matches!(dispatch(&registry, "!bank").await, Err(CommandError::UnknownCommand { name }) if name == "bank"));
+        assert!(dispatch(&registry, "no command here").await.is_ok());
+    }
+}
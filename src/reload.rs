@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::env::consts::DLL_EXTENSION;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use custom_error::custom_error;
+use log::{error, info, warn};
+
+use crate::context::PluginConfig;
+use crate::loader::{LoadedPlugin, LoaderError, PluginLoader};
+use crate::registry::{CommandRegistry, RegistryError, SharedRegistry};
+
+custom_error! { pub ReloadError
+    Io{path: String, source: std::io::Error} = "Unable to prepare plugin '{path}': {source}",
+    Load{source: LoaderError} = "{source}",
+    Registration{source: RegistryError} = "{source}"
+}
+
+/// The kind of change that was detected for a plugin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginChange {
+    Added,
+    Changed,
+    Removed,
+}
+
+/// The outcome of applying a change to a single plugin.
+pub struct ReloadReport {
+    /// The path of the plugin inside the watched directory
+    pub path: PathBuf,
+    pub change: PluginChange,
+    /// The commands that were registered (or unregistered, if the plugin was removed)
+    pub commands: Vec<String>,
+    /// Everything that went wrong while applying the change
+    pub errors: Vec<ReloadError>,
+}
+
+impl ReloadReport {
+    pub fn is_success(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// A copy of a plugin that is deleted once it is dropped.
+struct ShadowCopy {
+    path: PathBuf,
+}
+
+impl Drop for ShadowCopy {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(&self.path) {
+            warn!("Unable to remove shadow copy '{}': {}", self.path.display(), error);
+        }
+    }
+}
+
+struct PluginState {
+    modified: SystemTime,
+    // Field order matters: the plugin keeps its library loaded, so it has to be dropped before the
+    // copy the library was loaded from is deleted. Commands that are still held elsewhere, e.g. by a
+    // registry snapshot that is in use, keep the library loaded for longer; if the copy cannot be
+    // deleted then, that is logged.
+    plugin: Option<LoadedPlugin>,
+    /// The copy of the library that is actually loaded
+    shadow: Option<ShadowCopy>,
+    commands: Vec<String>,
+}
+
+/// Keeps the commands registered from a directory of plugins in sync with the directory contents.
+///
+/// Plugins are copied into a shadow directory before they are loaded, as most platforms refuse to
+/// load a library from a path that is still loaded, even if the file changed in the meantime.
+/// Every process uses its own shadow directory, which is removed when the `PluginDirectory` is dropped.
+pub struct PluginDirectory {
+    directory: PathBuf,
+    shadow_directory: PathBuf,
+    loader: PluginLoader,
+    plugins: HashMap<PathBuf, PluginState>,
+    generation: u64,
+}
+
+impl PluginDirectory {
+    /// Watches `directory`, using a directory inside the system's temporary directory for shadow copies.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        PluginDirectory::with_shadow_directory(directory, std::env::temp_dir().join("bpp-plugins"))
+    }
+
+    /// Watches `directory`, keeping shadow copies in a subdirectory of `shadow_directory` that is unique to this process.
+    pub fn with_shadow_directory<P: Into<PathBuf>, S: Into<PathBuf>>(directory: P, shadow_directory: S) -> Self {
+        PluginDirectory {
+            directory: directory.into(),
+            shadow_directory: shadow_directory.into().join(std::process::id().to_string()),
+            loader: PluginLoader::new(),
+            plugins: HashMap::new(),
+            generation: 0,
+        }
+    }
+
+    /// Compares the directory contents with the plugins that are currently known.
+    fn scan(&self) -> std::io::Result<Vec<(PathBuf, PluginChange, Option<SystemTime>)>> {
+        let mut found: HashMap<PathBuf, SystemTime> = HashMap::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            if !path.is_file() || path.extension() != Some(OsStr::new(DLL_EXTENSION)) {
+                continue;
+            }
+            found.insert(path, entry.metadata()?.modified()?);
+        }
+
+        let mut changes = Vec::new();
+        for (path, modified) in found.iter() {
+            match self.plugins.get(path) {
+                None => changes.push((path.clone(), PluginChange::Added, Some(*modified))),
+                Some(state) if state.modified != *modified => changes.push((path.clone(), PluginChange::Changed, Some(*modified))),
+                _ => {}
+            }
+        }
+        for path in self.plugins.keys() {
+            if !found.contains_key(path) {
+                changes.push((path.clone(), PluginChange::Removed, None));
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Applies every change in the directory since the last call.
+    ///
+    /// Commands of changed or removed plugins are unregistered first, then the reload waits for
+    /// their in-flight executions to finish before the new version of the plugin is loaded.
+    ///
+    /// The registry is only locked while it is copied and swapped (see [`SharedRegistry`]), never while
+    /// waiting for executions, so commands keep being dispatched during a reload.
+    pub async fn reload(&mut self, registry: &SharedRegistry) -> std::io::Result<Vec<ReloadReport>> {
+        let mut reports = Vec::new();
+        for (path, change, modified) in self.scan()? {
+            let mut report = ReloadReport {
+                path: path.clone(),
+                change,
+                commands: Vec::new(),
+                errors: Vec::new(),
+            };
+
+            // The configuration of commands is carried over to their new version
+            let mut configs: HashMap<String, PluginConfig> = HashMap::new();
+            if let Some(state) = self.plugins.remove(&path) {
+                configs = PluginDirectory::unload(state, registry, &mut report.errors).await;
+                if change == PluginChange::Removed {
+                    report.commands = configs.keys().cloned().collect();
+                }
+            }
+            if let Some(modified) = modified {
+                let state = self.load(&path, modified, registry, configs, &mut report).await;
+                self.plugins.insert(path, state);
+            }
+
+            if report.is_success() {
+                info!("Plugin '{}' {:?}: {}", report.path.display(), report.change, report.commands.join(", "));
+            } else {
+                for error in report.errors.iter() {
+                    error!("Plugin '{}' {:?}: {}", report.path.display(), report.change, error);
+                }
+            }
+            reports.push(report);
+        }
+        Ok(reports)
+    }
+
+    /// Reloads the directory every `interval`, forever.
+    pub async fn watch(mut self, registry: Arc<SharedRegistry>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(error) = self.reload(&registry).await {
+                error!("Unable to scan plugin directory '{}': {}", self.directory.display(), error);
+            }
+        }
+    }
+
+    /// Unregisters the commands of a plugin, returning their configuration by name.
+    async fn unload(state: PluginState, registry: &SharedRegistry, errors: &mut Vec<ReloadError>) -> HashMap<String, PluginConfig> {
+        let mut configs = HashMap::new();
+        registry.update(|registry| {
+            for name in state.commands.iter() {
+                let config = registry.config(name).unwrap_or_default();
+                match registry.unregister(name) {
+                    Ok(registered) => {
+                        configs.insert(registered.name, (*config).clone());
+                    }
+                    Err(error) => errors.push(error.into()),
+                }
+            }
+        });
+
+        if let Some(plugin) = state.plugin.as_ref() {
+            plugin.wait_idle().await;
+        }
+        configs
+    }
+
+    async fn load(&mut self, path: &Path, modified: SystemTime, registry: &SharedRegistry, mut configs: HashMap<String, PluginConfig>, report: &mut ReloadReport) -> PluginState {
+        let mut state = PluginState {
+            modified,
+            plugin: None,
+            shadow: None,
+            commands: Vec::new(),
+        };
+
+        self.generation += 1;
+        let file_stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let shadow_path = self.shadow_directory.join(format!("{}-{}.{}", file_stem, self.generation, DLL_EXTENSION));
+        let copied = fs::create_dir_all(&self.shadow_directory).and_then(|_| fs::copy(path, &shadow_path));
+        if let Err(source) = copied {
+            report.errors.push(ReloadError::Io {
+                path: path.display().to_string(),
+                source,
+            });
+            return state;
+        }
+        state.shadow = Some(ShadowCopy { path: shadow_path.clone() });
+
+        // Commands are loaded into a separate registry first, so collisions with other plugins
+        // can be attributed to this plugin without touching the commands that are already registered.
+        let mut staging = CommandRegistry::new();
+        let plugin = match self.loader.load(&shadow_path, &mut staging) {
+            Ok(plugin) => plugin,
+            Err(error) => {
+                report.errors.push(error.into());
+                return state;
+            }
+        };
+        report.errors.extend(staging.take_collisions().into_iter().map(ReloadError::from));
+
+        registry.update(|registry| {
+            for registered in staging.iter() {
+                match registry.try_register_group(registered.to_group()) {
+                    Ok(_) => {
+                        if let Some(config) = configs.remove(&registered.name) {
+                            if let Err(error) = registry.configure(&registered.name, config) {
+                                report.errors.push(error.into());
+                            }
+                        }
+                        state.commands.push(registered.name.clone());
+                    }
+                    Err(error) => report.errors.push(error.into()),
+                }
+            }
+        });
+        report.commands = state.commands.clone();
+        state.plugin = Some(plugin);
+        state
+    }
+}
+
+impl Drop for PluginDirectory {
+    fn drop(&mut self) {
+        // releases the plugins and deletes their shadow copies first
+        self.plugins.clear();
+        if let Err(error) = fs::remove_dir_all(&self.shadow_directory) {
+            if error.kind() != std::io::ErrorKind::NotFound {
+                warn!("Unable to remove shadow directory '{}': {}", self.shadow_directory.display(), error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+
+    fn plugin_path(directory: &Path, name: &str) -> PathBuf {
+        directory.join(format!("{}.{}", name, DLL_EXTENSION))
+    }
+
+    /// Writes a file that looks like a plugin, but cannot be loaded.
+    fn write_plugin(path: &Path, modified: SystemTime) {
+        fs::write(path, b"not a library").unwrap();
+        File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+    }
+
+    fn changes(reports: &[ReloadReport]) -> Vec<(String, PluginChange)> {
+        let mut changes: Vec<(String, PluginChange)> = reports.iter()
+            .map(|report| (report.path.file_stem().unwrap().to_string_lossy().to_string(), report.change))
+            .collect();
+        changes.sort_by(|a, b| a.0.cmp(&b.0));
+        changes
+    }
+
+    #[tokio::test]
+    async fn added_changed_and_removed_plugins_are_detected() {
+        let directory = tempfile::tempdir().unwrap();
+        let shadow_directory = tempfile::tempdir().unwrap();
+        let registry = SharedRegistry::default();
+        let mut plugins = PluginDirectory::with_shadow_directory(directory.path(), shadow_directory.path());
+        let epoch = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+
+        write_plugin(&plugin_path(directory.path(), "dice"), epoch);
+        write_plugin(&plugin_path(directory.path(), "points"), epoch);
+        fs::write(directory.path().join("notes.txt"), b"not a plugin").unwrap();
+        let reports = plugins.reload(&registry).await.unwrap();
+        assert_eq!(changes(&reports), vec![("dice".to_string(), PluginChange::Added), ("points".to_string(), PluginChange::Added)]);
+        for report in reports.iter() {
+            assert!(matches!(report.errors.as_slice(), [ReloadError::Load { .. }]));
+        }
+
+        // nothing changed since the last scan
+        assert!(plugins.reload(&registry).await.unwrap().is_empty());
+
+        write_plugin(&plugin_path(directory.path(), "dice"), epoch + Duration::from_secs(60));
+        fs::remove_file(plugin_path(directory.path(), "points")).unwrap();
+        let reports = plugins.reload(&registry).await.unwrap();
+        assert_eq!(changes(&reports), vec![("dice".to_string(), PluginChange::Changed), ("points".to_string(), PluginChange::Removed)]);
+        let removed = reports.iter().find(|report| report.change == PluginChange::Removed).unwrap();
+        assert!(removed.is_success());
+
+        assert!(plugins.reload(&registry).await.unwrap().is_empty());
+        assert!(registry.snapshot().is_empty());
+    }
+
+    #[tokio::test]
+    async fn shadow_copies_are_deleted() {
+        let directory = tempfile::tempdir().unwrap();
+        let shadow_directory = tempfile::tempdir().unwrap();
+        let registry = SharedRegistry::default();
+        let mut plugins = PluginDirectory::with_shadow_directory(directory.path(), shadow_directory.path());
+        let process_directory = shadow_directory.path().join(std::process::id().to_string());
+
+        write_plugin(&plugin_path(directory.path(), "dice"), SystemTime::now());
+        plugins.reload(&registry).await.unwrap();
+        assert_eq!(fs::read_dir(&process_directory).unwrap().count(), 1);
+
+        // the copy of the old version is deleted once the new version is loaded
+        write_plugin(&plugin_path(directory.path(), "dice"), SystemTime::now() + Duration::from_secs(60));
+        plugins.reload(&registry).await.unwrap();
+        assert_eq!(fs::read_dir(&process_directory).unwrap().count(), 1);
+
+        drop(plugins);
+        assert!(!process_directory.exists());
+    }
+}
@@ -0,0 +1,158 @@
+use tonic::transport::Channel;
+
+use crate::{CommandError, MAX_CHAT_MESSAGE_LENGTH};
+use crate::youtubeservice::SendMessageRequest;
+use crate::youtubeservice::you_tube_service_client::YouTubeServiceClient;
+
+/// Splits `text` into messages of at most `limit` characters.
+///
+/// Text is split at whitespace where possible; words that are longer than `limit` are split wherever needed.
+/// Whitespace inside a message is kept as it is, while whitespace at a split is dropped.
+pub fn split_message(text: &str, limit: usize) -> Vec<String> {
+    let limit = limit.max(1);
+    let mut messages: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+    // the whitespace before the next word, only added once the word fits into the current message
+    let mut separator = "";
+    let mut rest = text;
+    while !rest.is_empty() {
+        let word_start = rest.find(|c: char| !c.is_whitespace()).unwrap_or(rest.len());
+        let (whitespace, after) = rest.split_at(word_start);
+        if current_len > 0 {
+            separator = whitespace;
+        }
+        let word_end = after.find(char::is_whitespace).unwrap_or(after.len());
+        let (word, after) = after.split_at(word_end);
+        rest = after;
+        if word.is_empty() {
+            break;
+        }
+
+        let word_len = word.chars().count();
+        let separator_len = separator.chars().count();
+        if current_len > 0 && current_len + separator_len + word_len <= limit {
+            current.push_str(separator);
+            current.push_str(word);
+            current_len += separator_len + word_len;
+            continue;
+        }
+        if current_len > 0 {
+            messages.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+        for c in word.chars() {
+            if current_len == limit {
+                messages.push(std::mem::take(&mut current));
+                current_len = 0;
+            }
+            current.push(c);
+            current_len += 1;
+        }
+    }
+    if current_len > 0 {
+        messages.push(current);
+    }
+    messages
+}
+
+/// A handle for sending messages back to the chat a command was invoked from.
+///
+/// Long texts are split into multiple chat messages automatically.
+#[derive(Clone)]
+pub struct Reply {
+    client: YouTubeServiceClient<Channel>,
+    /// The display name of the user that invoked the command
+    display_name: String,
+}
+
+impl Reply {
+    pub fn new(client: YouTubeServiceClient<Channel>, display_name: String) -> Self {
+        Reply { client, display_name }
+    }
+
+    /// Sends `text` to the chat.
+    pub async fn reply(&mut self, text: &str) -> Result<(), CommandError> {
+        for message in split_message(text, MAX_CHAT_MESSAGE_LENGTH) {
+            self.send(message).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends `text` to the chat, mentioning the user that invoked the command.
+    pub async fn reply_to_user(&mut self, text: &str) -> Result<(), CommandError> {
+        let text = format!("@{} {}", self.display_name, text);
+        self.reply(&text).await
+    }
+
+    /// Sends `text` to the chat, addressed to the user that invoked the command.
+    ///
+    /// YouTube chat has no private messages, so unlike [`reply_to_user`](Reply::reply_to_user),
+    /// every message of a split text mentions the user.
+    pub async fn whisper(&mut self, text: &str) -> Result<(), CommandError> {
+        let mention = format!("@{} ", self.display_name);
+        let limit = MAX_CHAT_MESSAGE_LENGTH.saturating_sub(mention.chars().count());
+        for message in split_message(text, limit) {
+            // the mention alone can exceed the limit for very long display names
+            let message: String = format!("{}{}", mention, message).chars().take(MAX_CHAT_MESSAGE_LENGTH).collect();
+            self.send(message).await?;
+        }
+        Ok(())
+    }
+
+    async fn send(&mut self, message: String) -> Result<(), CommandError> {
+        self.client.send_message(SendMessageRequest { message }).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_text_is_kept_as_is() {
+        assert_eq!(split_message("hello  world", 20), vec!["hello  world"]);
+        assert_eq!(split_message("  padded  ", 20), vec!["padded"]);
+        assert!(split_message("", 20).is_empty());
+        assert!(split_message(" \n ", 20).is_empty());
+    }
+
+    #[test]
+    fn text_is_split_at_word_boundaries() {
+        assert_eq!(split_message("one two three four", 9), vec!["one two", "three", "four"]);
+        assert_eq!(split_message("one two three", 7), vec!["one two", "three"]);
+    }
+
+    #[test]
+    fn whitespace_inside_a_message_is_kept() {
+        assert_eq!(split_message("line one\nline two", 20), vec!["line one\nline two"]);
+        assert_eq!(split_message("a\tb  c\n\nd", 20), vec!["a\tb  c\n\nd"]);
+        // whitespace counts towards the limit, and is dropped where the text is split
+        assert_eq!(split_message("ab    cd", 6), vec!["ab", "cd"]);
+        assert_eq!(split_message("first\n\nsecond", 10), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn long_words_are_split_at_the_limit() {
+        assert_eq!(split_message("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(split_message("hi abcdefghij yo", 4), vec!["hi", "abcd", "efgh", "ij", "yo"]);
+        assert_eq!(split_message("abcdefgh", 4), vec!["abcd", "efgh"]);
+    }
+
+    #[test]
+    fn limits_count_characters_not_bytes() {
+        assert_eq!(split_message("äöü ßéè", 3), vec!["äöü", "ßéè"]);
+        assert_eq!(split_message("äöüß", 3), vec!["äöü", "ß"]);
+        assert_eq!(split_message("🎲🎲 🎲", 4), vec!["🎲🎲 🎲"]);
+        assert_eq!(split_message("🎲🎲🎲🎲🎲", 2), vec!["🎲🎲", "🎲🎲", "🎲"]);
+        for message in split_message(&"ü".repeat(450), MAX_CHAT_MESSAGE_LENGTH) {
+            assert!(message.chars().count() <= MAX_CHAT_MESSAGE_LENGTH);
+        }
+    }
+
+    #[test]
+    fn a_zero_limit_still_makes_progress() {
+        assert_eq!(split_message("ab", 0), vec!["a", "b"]);
+    }
+}
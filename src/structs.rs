@@ -1,18 +1,130 @@
-use chrono::NaiveDateTime;
+use std::convert::TryFrom;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use custom_error::custom_error;
 use log::error;
 use super::userservice::user_service_client::UserServiceClient;
 use super::youtubeservice::you_tube_service_client::YouTubeServiceClient;
 use tonic::transport::Channel;
 
-use crate::message::StringView;
+use crate::args::{ArgumentError, ArgumentReader, CommandArgs};
+use crate::cooldown::Cooldowns;
+use crate::economy::Economy;
+use crate::flags::{FlagError, OptionParser, OptionSpec, ParsedArguments};
+use crate::message::{CommandPrefixes, ParserConfig, StringView, StringViewError};
+use crate::permissions::{PermissionResolver, Requirements};
+use crate::ranks::RankHierarchy;
+use crate::reply::Reply;
+use crate::traits::Command;
+use crate::users::UserResolver;
 
-fn from_prost_timestamp(prost_timestamp: &prost_types::Timestamp) -> NaiveDateTime {
-    NaiveDateTime::from_timestamp(prost_timestamp.seconds, prost_timestamp.nanos as u32)
+custom_error! { pub ConversionError
+    MissingField{field: String} = "The user record is missing the field '{field}'",
+    InvalidTimestamp{field: String} = "The user record contains an invalid timestamp in the field '{field}'"
+}
+
+/// How to convert a user record that lacks `first_seen_at`, `last_seen_at` or `hours`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingTimestampPolicy {
+    /// Fail with [`ConversionError::MissingField`]
+    #[default]
+    Error,
+    /// Use the Unix epoch for missing timestamps and no active time for missing hours
+    UnixEpoch,
+    /// Use the current time for missing timestamps and no active time for missing hours
+    Now,
+}
+
+impl MissingTimestampPolicy {
+    fn timestamp(&self, timestamp: Option<&prost_types::Timestamp>, field: &str) -> Result<NaiveDateTime, ConversionError> {
+        match (timestamp, self) {
+            (Some(timestamp), _) => from_prost_timestamp(timestamp).ok_or_else(|| ConversionError::InvalidTimestamp { field: field.to_string() }),
+            (None, MissingTimestampPolicy::Error) => Err(ConversionError::MissingField { field: field.to_string() }),
+            (None, MissingTimestampPolicy::UnixEpoch) => Ok(DateTime::from_timestamp(0, 0).unwrap().naive_utc()),
+            (None, MissingTimestampPolicy::Now) => Ok(Utc::now().naive_utc()),
+        }
+    }
+}
+
+fn from_prost_timestamp(prost_timestamp: &prost_types::Timestamp) -> Option<NaiveDateTime> {
+    if prost_timestamp.nanos < 0 {
+        return None;
+    }
+    DateTime::from_timestamp(prost_timestamp.seconds, prost_timestamp.nanos as u32).map(|date_time| date_time.naive_utc())
 }
 
 pub struct ServiceDirectory<'a> {
     pub userservice_client: &'a mut UserServiceClient<Channel>,
     pub youtubeservice_client: &'a mut YouTubeServiceClient<Channel>,
+    /// Changes balances of users, shared by all commands so concurrent updates do not get lost
+    ///
+    /// This is `None` if the host does not provide an economy.
+    pub economy: Option<&'a Economy>,
+}
+
+impl ServiceDirectory<'_> {
+    /// Creates a handle for replying to the user that sent `message`.
+    pub fn reply_to(&self, message: &Message) -> Reply {
+        Reply::new(self.youtubeservice_client.clone(), message.user.display_name.clone())
+    }
+
+    /// Creates a resolver for looking up the users that commands refer to.
+    ///
+    /// Lookups by name share the user list of the resolver, so keep it for looking up several users.
+    pub fn users(&self) -> UserResolver {
+        UserResolver::new(self.userservice_client.clone())
+    }
+}
+
+/// Information about a command that is provided when registering it.
+#[derive(Clone, Debug, Default)]
+pub struct CommandMeta {
+    /// A short, one-line description of what the command does
+    pub description: Option<String>,
+    /// The arguments the command expects, e.g. `<user> <amount>`
+    pub usage: Option<String>,
+    /// Example invocations without the prefix, e.g. `give someone 10`
+    pub examples: Vec<String>,
+    /// The category the command is listed under
+    pub category: Option<String>,
+    /// Hidden commands are left out of command listings
+    pub hidden: bool,
+    /// What a user needs in order to run the command, enforced before the command is executed
+    pub requirements: Requirements,
+    /// How often the command may be used, enforced before the command is executed
+    pub cooldowns: Cooldowns,
+}
+
+/// A command with nested subcommands, such as `!bank deposit` and `!bank withdraw`.
+///
+/// Every subcommand has its own aliases and metadata, including requirements and help texts.
+#[derive(Clone)]
+pub struct CommandGroup {
+    pub name: String,
+    pub aliases: Vec<String>,
+    /// The command that runs if no subcommand matches
+    ///
+    /// If this is `None`, invoking the group without a valid subcommand replies with its usage.
+    pub command: Option<Box<dyn Command>>,
+    pub meta: CommandMeta,
+    pub subcommands: Vec<CommandGroup>,
+}
+
+impl CommandGroup {
+    pub fn new(name: &str, aliases: &[&str], command: Option<Box<dyn Command>>, meta: CommandMeta) -> Self {
+        CommandGroup {
+            name: name.to_string(),
+            aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+            command,
+            meta,
+            subcommands: Vec::new(),
+        }
+    }
+
+    /// Adds a subcommand to the group.
+    pub fn subcommand(mut self, subcommand: CommandGroup) -> Self {
+        self.subcommands.push(subcommand);
+        self
+    }
 }
 
 /// A user that sent a message.
@@ -28,24 +140,81 @@ pub struct CommandUser {
     pub permissions: Vec<super::userservice::Permission>
 }
 
-impl From<super::userservice::BppUser> for CommandUser {
-    fn from(user: super::userservice::BppUser) -> Self {
-        let first_seen = from_prost_timestamp(user.first_seen_at.as_ref().unwrap());
-        let last_seen = from_prost_timestamp(user.last_seen_at.as_ref().unwrap());
-        CommandUser {
+impl CommandUser {
+    /// Checks whether the user is a member of the given group.
+    pub fn in_group(&self, group: &str) -> bool {
+        self.groups.iter().any(|bpp_group| bpp_group.name.eq_ignore_ascii_case(group))
+    }
+
+    /// Checks whether the user's rank is `minimum` or a higher rank in `hierarchy`.
+    pub fn rank_at_least(&self, minimum: &str, hierarchy: &RankHierarchy) -> bool {
+        hierarchy.is_at_least(&self.rank, minimum)
+    }
+
+    /// Checks whether the user holds a permission, either directly or through one of their groups.
+    ///
+    /// Supports wildcards and negations, but no group priorities or inheritance, see [`CommandUser::has_permission_with`].
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.has_permission_with(permission, &PermissionResolver::default())
+    }
+
+    /// Checks whether the user holds a permission, using the group configuration of `resolver`.
+    pub fn has_permission_with(&self, permission: &str, resolver: &PermissionResolver) -> bool {
+        resolver.has_permission(self, permission)
+    }
+}
+
+impl CommandUser {
+    /// Converts a user record from the userservice, filling in missing timestamps according to `policy`.
+    pub fn from_bpp_user(user: super::userservice::BppUser, policy: MissingTimestampPolicy) -> Result<Self, ConversionError> {
+        let first_seen = policy.timestamp(user.first_seen_at.as_ref(), "first_seen_at")?;
+        let last_seen = policy.timestamp(user.last_seen_at.as_ref(), "last_seen_at")?;
+        let active_time = match (user.hours, policy) {
+            (Some(hours), _) => hours.seconds,
+            (None, MissingTimestampPolicy::Error) => return Err(ConversionError::MissingField { field: "hours".to_string() }),
+            (None, _) => 0,
+        };
+        Ok(CommandUser {
             channel_id: user.channel_id,
             display_name: user.display_name,
-            active_time: user.hours.unwrap().seconds,
+            active_time,
             money: user.money,
             first_seen_at: first_seen,
             last_seen_at: last_seen,
             rank: user.rank,
             groups: user.groups,
             permissions: user.permissions
+        })
+    }
+}
+
+#[cfg(test)]
+impl CommandUser {
+    /// A user without rank, groups or permissions that was first and last seen at the Unix epoch.
+    pub(crate) fn test_user(channel_id: &str) -> Self {
+        CommandUser {
+            channel_id: channel_id.to_string(),
+            display_name: channel_id.to_string(),
+            active_time: 0,
+            money: 0.0,
+            first_seen_at: NaiveDateTime::default(),
+            last_seen_at: NaiveDateTime::default(),
+            rank: String::new(),
+            groups: Vec::new(),
+            permissions: Vec::new(),
         }
     }
 }
 
+impl TryFrom<super::userservice::BppUser> for CommandUser {
+    type Error = ConversionError;
+
+    /// Converts a user record, failing if any timestamp is missing or invalid.
+    fn try_from(user: super::userservice::BppUser) -> Result<Self, Self::Error> {
+        CommandUser::from_bpp_user(user, MissingTimestampPolicy::Error)
+    }
+}
+
 impl From<CommandUser> for super::userservice::BppUser {
     fn from(user: CommandUser) -> Self {
         super::userservice::BppUser {
@@ -57,12 +226,12 @@ impl From<CommandUser> for super::userservice::BppUser {
             }),
             money: user.money,
             first_seen_at: Some(prost_types::Timestamp {
-                seconds: user.first_seen_at.timestamp(),
-                nanos: user.first_seen_at.timestamp_subsec_nanos() as i32,
+                seconds: user.first_seen_at.and_utc().timestamp(),
+                nanos: user.first_seen_at.and_utc().timestamp_subsec_nanos() as i32,
             }),
             last_seen_at: Some(prost_types::Timestamp {
-                seconds: user.last_seen_at.timestamp(),
-                nanos: user.last_seen_at.timestamp_subsec_nanos() as i32,
+                seconds: user.last_seen_at.and_utc().timestamp(),
+                nanos: user.last_seen_at.and_utc().timestamp_subsec_nanos() as i32,
             }),
             groups: user.groups,
             rank: user.rank,
@@ -80,56 +249,197 @@ pub struct Message {
     /// The raw message
     pub message: String,
     /// Flag indicating if this message can be used for command parsing
+    ///
+    /// This is only true if the message started with a command prefix.
     pub has_command_info: bool,
+    /// The prefix the message started with
+    ///
+    /// This is only set if `has_command_info` is true or `parse_error` is set.
+    pub prefix: String,
     /// The command that was sent
     ///
-    /// This is only set if `has_command_info` is true and is usually the first word of a message.
+    /// This is only set if `has_command_info` is true and is the first word after the prefix.
     pub command_name: String,
     /// The arguments that were sent with the command
     ///
     /// If `has_command_info` is false, this will be empty. This can also be empty if there were simply no arguments.
     pub command_args: Vec<String>,
+    /// Whether each of `command_args` was enclosed in quotes
+    ///
+    /// Quoted arguments such as `"-s"` are never treated as options.
+    pub command_args_quoted: Vec<bool>,
+    /// The error that occurred while splitting the message into command and arguments
+    ///
+    /// Its span is relative to `message`. If this is set, `has_command_info` is false.
+    pub parse_error: Option<StringViewError>,
 }
 
 impl Message {
+    /// Creates a reader for parsing typed arguments from `command_args`.
+    pub fn arguments(&self) -> ArgumentReader<'_> {
+        ArgumentReader::new(&self.command_args)
+    }
+
+    /// Splits `command_args` into positional arguments and the options described by `specs`.
+    pub fn parse_options(&self, specs: Vec<OptionSpec>) -> Result<ParsedArguments, FlagError> {
+        OptionParser::new(specs).parse_quoted(&self.command_args, &self.command_args_quoted)
+    }
+
+    /// Parses all of `command_args` into `T`, usually a struct deriving [`CommandArgs`].
+    pub fn parse_arguments<T: CommandArgs>(&self) -> Result<T, ArgumentError> {
+        T::from_args(&self.command_args)
+    }
+
+    /// Creates a new Message from a user and a raw String, using the default prefix `!`
+    ///
+    /// See [`Message::with_prefixes`].
+    pub fn new(user: CommandUser, message: String) -> Message {
+        Message::with_prefixes(user, message, &CommandPrefixes::default())
+    }
+
     /// Creates a new Message from a user and a raw String
     ///
-    /// If a StringView cannot determine the command structure, it will set `has_command_info` to false.
+    /// If the message starts with one of the prefixes and a StringView can determine the command structure,
+    /// it will set `has_command_info` to true and set the prefix, command name and arguments.
     ///
-    /// If the message is a command, it will set `has_command_info` to true and set the command name and arguments.
-    pub fn new(user: CommandUser, message: String) -> Message {
-        let message_copy = message.clone();
-        let mut message_view = StringView::new(message);
-        let args = message_view.get_parameters();
-        if let Ok(args) = args {
-            let command_name = args.first().unwrap().clone();
-            if args.len() > 1 {
-                let command_args: Vec<String> = args.iter().skip(1).cloned().collect();
-                Message {
-                    user,
-                    message: message_copy,
-                    command_name,
-                    command_args,
-                    has_command_info: true,
+    /// Otherwise it will set `has_command_info` to false.
+    pub fn with_prefixes(user: CommandUser, message: String, prefixes: &CommandPrefixes) -> Message {
+        Message::with_config(user, message, prefixes, &ParserConfig::default())
+    }
+
+    /// Creates a new Message like [`Message::with_prefixes`], splitting the arguments according to `config`.
+    pub fn with_config(user: CommandUser, message: String, prefixes: &CommandPrefixes, config: &ParserConfig) -> Message {
+        let stripped = prefixes.strip(&message).map(|(prefix, rest)| (prefix.to_string(), rest.to_string()));
+        let mut result = Message {
+            user,
+            message,
+            has_command_info: false,
+            prefix: String::new(),
+            command_name: String::new(),
+            command_args: Vec::new(),
+            command_args_quoted: Vec::new(),
+            parse_error: None,
+        };
+
+        if let Some((prefix, rest)) = stripped {
+            // `rest` is always the end of `message`
+            let offset = result.message.len() - rest.len();
+            let mut message_view = StringView::with_config(rest, config.clone());
+            match message_view.get_arguments() {
+                Ok(mut args) if !args.is_empty() => {
+                    result.command_name = args.remove(0).0;
+                    let (args, quoted) = args.into_iter().unzip();
+                    result.command_args = args;
+                    result.command_args_quoted = quoted;
+                    result.prefix = prefix;
+                    result.has_command_info = true;
                 }
-            } else {
-                Message {
-                    user,
-                    message: message_copy,
-                    command_name,
-                    command_args: Vec::new(),
-                    has_command_info: true,
+                Ok(_) => {}
+                Err(error) => {
+                    let error = error.shifted(offset);
+                    error!("Unable to get parameters from message: {}", error.render_verbose(&result.message));
+                    result.prefix = prefix;
+                    result.parse_error = Some(error);
                 }
             }
-        } else {
-            error!("Unable to get parameters from message: {}", args.unwrap_err());
-            Message {
-                user,
-                message: message_copy,
-                has_command_info: false,
-                command_name: String::new(),
-                command_args: Vec::new(),
-            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::userservice::BppUser;
+
+    fn timestamp(seconds: i64, nanos: i32) -> Option<prost_types::Timestamp> {
+        Some(prost_types::Timestamp { seconds, nanos })
+    }
+
+    fn complete_record() -> BppUser {
+        BppUser {
+            channel_id: "alice".to_string(),
+            display_name: "Alice".to_string(),
+            hours: Some(prost_types::Duration { seconds: 7_200, nanos: 0 }),
+            first_seen_at: timestamp(1_600_000_000, 500),
+            last_seen_at: timestamp(1_700_000_000, 0),
+            ..BppUser::default()
         }
     }
-}
\ No newline at end of file
+
+    fn missing_everything() -> BppUser {
+        BppUser {
+            hours: None,
+            first_seen_at: None,
+            last_seen_at: None,
+            ..complete_record()
+        }
+    }
+
+    fn missing_field(result: Result<CommandUser, ConversionError>) -> String {
+        match result {
+            Err(ConversionError::MissingField { field }) => field,
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("the record was converted"),
+        }
+    }
+
+    #[test]
+    fn complete_records_convert_under_every_policy() {
+        for policy in [MissingTimestampPolicy::Error, MissingTimestampPolicy::UnixEpoch, MissingTimestampPolicy::Now].iter() {
+            let user = CommandUser::from_bpp_user(complete_record(), *policy).unwrap();
+            assert_eq!(user.active_time, 7_200);
+            assert_eq!(user.first_seen_at.and_utc().timestamp(), 1_600_000_000);
+            assert_eq!(user.first_seen_at.and_utc().timestamp_subsec_nanos(), 500);
+            assert_eq!(user.last_seen_at.and_utc().timestamp(), 1_700_000_000);
+        }
+    }
+
+    #[test]
+    fn the_error_policy_rejects_missing_fields() {
+        assert_eq!(MissingTimestampPolicy::default(), MissingTimestampPolicy::Error);
+        let convert = |user: BppUser| CommandUser::try_from(user);
+
+        assert_eq!(missing_field(convert(BppUser { first_seen_at: None, ..complete_record() })), "first_seen_at");
+        assert_eq!(missing_field(convert(BppUser { last_seen_at: None, ..complete_record() })), "last_seen_at");
+        assert_eq!(missing_field(convert(BppUser { hours: None, ..complete_record() })), "hours");
+    }
+
+    #[test]
+    fn the_unix_epoch_policy_fills_in_the_epoch() {
+        let user = CommandUser::from_bpp_user(missing_everything(), MissingTimestampPolicy::UnixEpoch).unwrap();
+
+        assert_eq!(user.first_seen_at.and_utc().timestamp(), 0);
+        assert_eq!(user.last_seen_at.and_utc().timestamp(), 0);
+        assert_eq!(user.active_time, 0);
+    }
+
+    #[test]
+    fn the_now_policy_fills_in_the_current_time() {
+        let before = Utc::now().naive_utc();
+        let user = CommandUser::from_bpp_user(missing_everything(), MissingTimestampPolicy::Now).unwrap();
+        let after = Utc::now().naive_utc();
+
+        assert!(before <= user.first_seen_at && user.first_seen_at <= after);
+        assert!(before <= user.last_seen_at && user.last_seen_at <= after);
+        assert_eq!(user.active_time, 0);
+    }
+
+    #[test]
+    fn invalid_timestamps_are_rejected_under_every_policy() {
+        for policy in [MissingTimestampPolicy::Error, MissingTimestampPolicy::UnixEpoch, MissingTimestampPolicy::Now].iter() {
+            let negative_nanos = BppUser { first_seen_at: timestamp(0, -1), ..complete_record() };
+            let out_of_range = BppUser { last_seen_at: timestamp(i64::MAX, 0), ..complete_record() };
+
+            assert!(matches!(CommandUser::from_bpp_user(negative_nanos, *policy), Err(ConversionError::InvalidTimestamp { field }) if field == "first_seen_at"));
+            assert!(matches!(CommandUser::from_bpp_user(out_of_range, *policy), Err(ConversionError::InvalidTimestamp { field }) if field == "last_seen_at"));
+        }
+    }
+
+    #[test]
+    fn converted_users_round_trip() {
+        let record = BppUser::from(CommandUser::try_from(complete_record()).unwrap());
+
+        assert_eq!(record, complete_record());
+    }
+}
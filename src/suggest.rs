@@ -0,0 +1,206 @@
+use crate::registry::CommandRegistry;
+use crate::structs::CommandUser;
+
+/// A registered command that is similar to an unknown command name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Suggestion {
+    /// The primary name of the command
+    pub name: String,
+    /// The name or alias of the command that was closest to the unknown name
+    pub matched: String,
+    /// The edit distance between the unknown name and `matched`
+    pub distance: usize,
+    /// Whether `matched` starts with the unknown name
+    pub is_prefix: bool,
+}
+
+/// Counts the insertions, deletions, substitutions and swaps of adjacent characters needed to turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // Rows for i - 2, i - 1 and i of the optimal string alignment matrix
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current: Vec<usize> = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            current[j] = (previous[j] + 1).min(current[j - 1] + 1).min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// The largest edit distance at which a name is still suggested.
+fn max_distance(name: &str) -> usize {
+    (name.chars().count() / 3).max(1)
+}
+
+impl CommandRegistry {
+    /// Finds registered commands that are similar to the unknown command `name`.
+    ///
+    /// Commands are ranked by the edit distance of their closest name or alias, with names that
+    /// start with `name` ranked first among equals. Hidden commands are never suggested, and if
+    /// `user` is given, neither are commands the user is not allowed to run.
+    pub fn suggest(&self, name: &str, user: Option<&CommandUser>, limit: usize) -> Vec<Suggestion> {
+        let name = name.to_lowercase();
+        if name.is_empty() {
+            return Vec::new();
+        }
+
+        let mut suggestions: Vec<Suggestion> = Vec::new();
+        for registered in self.iter() {
+            if registered.meta.hidden {
+                continue;
+            }
+            if let Some(user) = user {
+                if registered.meta.requirements.check_with(user, self.permission_resolver(), self.rank_hierarchy()).is_err() {
+                    continue;
+                }
+            }
+
+            let best = std::iter::once(&registered.name)
+                .chain(registered.aliases.iter())
+                .map(|candidate| {
+                    let lowercase = candidate.to_lowercase();
+                    Suggestion {
+                        name: registered.name.clone(),
+                        matched: candidate.clone(),
+                        distance: edit_distance(&name, &lowercase),
+                        is_prefix: name.chars().count() > 1 && lowercase.starts_with(&name),
+                    }
+                })
+                .filter(|suggestion| suggestion.is_prefix || suggestion.distance <= max_distance(&name))
+                .min_by_key(|suggestion| (suggestion.distance, !suggestion.is_prefix));
+            if let Some(best) = best {
+                suggestions.push(best);
+            }
+        }
+
+        suggestions.sort_by(|a, b| {
+            (a.distance, !a.is_prefix, &a.name).cmp(&(b.distance, !b.is_prefix, &b.name))
+        });
+        suggestions.truncate(limit);
+        suggestions
+    }
+
+    /// Renders a reply such as "Did you mean !addcan?" for an unknown command, if there are any suggestions.
+    pub fn did_you_mean(&self, name: &str, user: Option<&CommandUser>, prefix: &str) -> Option<String> {
+        let suggestions: Vec<String> = self.suggest(name, user, 3).into_iter()
+            .map(|suggestion| format!("{}{}", prefix, suggestion.matched))
+            .collect();
+        match suggestions.split_last() {
+            None => None,
+            Some((last, [])) => Some(format!("Did you mean {}?", last)),
+            Some((last, rest)) => Some(format!("Did you mean {} or {}?", rest.join(", "), last)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::CommandError;
+    use crate::context::CommandContext;
+    use crate::structs::CommandMeta;
+    use crate::traits::Command;
+
+    #[derive(Clone)]
+    struct Noop;
+
+    #[async_trait]
+    impl Command for Noop {
+        async fn run(&self, _context: CommandContext<'_, '_>) -> Result<(), CommandError> {
+            Ok(())
+        }
+    }
+
+    fn registry(commands: &[(&str, &[&str])]) -> CommandRegistry {
+        let mut registry = CommandRegistry::new();
+        for (name, aliases) in commands {
+            registry.try_register(name, aliases, Box::new(Noop), CommandMeta::default()).unwrap();
+        }
+        registry
+    }
+
+    fn matched(suggestions: Vec<Suggestion>) -> Vec<String> {
+        suggestions.into_iter().map(|suggestion| suggestion.matched).collect()
+    }
+
+    #[test]
+    fn edit_distance_counts_single_edits() {
+        assert_eq!(edit_distance("give", "give"), 0);
+        assert_eq!(edit_distance("give", "gives"), 1);
+        assert_eq!(edit_distance("give", "gve"), 1);
+        assert_eq!(edit_distance("give", "gove"), 1);
+        assert_eq!(edit_distance("", "give"), 4);
+        assert_eq!(edit_distance("give", ""), 4);
+    }
+
+    #[test]
+    fn edit_distance_counts_adjacent_swaps_once() {
+        assert_eq!(edit_distance("gvie", "give"), 1);
+        assert_eq!(edit_distance("ab", "ba"), 1);
+        // optimal string alignment does not edit a substring twice
+        assert_eq!(edit_distance("ca", "abc"), 3);
+    }
+
+    #[test]
+    fn edit_distance_works_on_chars() {
+        assert_eq!(edit_distance("grüße", "grusse"), 3);
+        assert_eq!(edit_distance("🎲roll", "roll"), 1);
+    }
+
+    #[test]
+    fn allowed_distance_grows_with_the_name() {
+        assert_eq!(max_distance("a"), 1);
+        assert_eq!(max_distance("give"), 1);
+        assert_eq!(max_distance("points"), 2);
+        assert_eq!(max_distance("leaderboard"), 3);
+    }
+
+    #[test]
+    fn close_names_and_aliases_are_suggested() {
+        let registry = registry(&[("give", &["transfer"]), ("points", &[]), ("roll", &[])]);
+
+        assert_eq!(matched(registry.suggest("gvie", None, 3)), vec!["give"]);
+        assert_eq!(matched(registry.suggest("tranfser", None, 3)), vec!["transfer"]);
+        assert_eq!(matched(registry.suggest("GIVE", None, 3)), vec!["give"]);
+    }
+
+    #[test]
+    fn names_beyond_the_threshold_are_not_suggested() {
+        let registry = registry(&[("give", &[]), ("points", &[])]);
+
+        assert!(registry.suggest("gxyz", None, 3).is_empty());
+        assert!(registry.suggest("pnts", None, 3).is_empty());
+        assert_eq!(matched(registry.suggest("pints", None, 3)), vec!["points"]);
+        assert!(registry.suggest("pxxts", None, 3).is_empty());
+        assert!(registry.suggest("", None, 3).is_empty());
+    }
+
+    #[test]
+    fn prefixes_are_suggested_and_ranked_first_among_equals() {
+        let registry = registry(&[("leaderboard", &[]), ("lead", &[]), ("leaf", &[])]);
+
+        assert_eq!(matched(registry.suggest("lea", None, 3)), vec!["lead", "leaf", "leaderboard"]);
+        assert_eq!(matched(registry.suggest("lea", None, 1)), vec!["lead"]);
+    }
+
+    #[test]
+    fn did_you_mean_lists_the_suggestions() {
+        let registry = registry(&[("give", &[]), ("gift", &[])]);
+
+        assert_eq!(registry.did_you_mean("gi", None, "!"), Some("Did you mean !gift or !give?".to_string()));
+        assert_eq!(registry.did_you_mean("zzzz", None, "!"), None);
+    }
+}
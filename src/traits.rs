@@ -2,18 +2,74 @@ use async_trait::async_trait;
 use dyn_clone::DynClone;
 
 use super::CommandError;
-use crate::structs::{Message, ServiceDirectory};
+use crate::context::CommandContext;
+use crate::structs::{CommandGroup, CommandMeta, Message, ServiceDirectory};
 
 /// Types that implement this trait can be registered as a command handler.
 ///
+/// Commands written against the older `execute` signature implement [`LegacyCommand`] instead,
+/// which makes them a `Command` as well.
+///
 /// This trait is an async_trait, which means that you can use async/await syntax.
 #[async_trait]
 pub trait Command: Send + Sync + DynClone {
-    async fn execute(&self, message: Message, service_directory: &mut ServiceDirectory) -> Result<(), CommandError>;
+    async fn run(&self, context: CommandContext<'_, '_>) -> Result<(), CommandError>;
 }
 dyn_clone::clone_trait_object!(Command);
 
+/// Commands that use the signature from before [`CommandContext`] existed.
+///
+/// Every `LegacyCommand` is a [`Command`] whose [`run`](Command::run) passes the message and the
+/// services on to [`execute`](LegacyCommand::execute).
+#[async_trait]
+pub trait LegacyCommand: Send + Sync + DynClone {
+    async fn execute(&self, message: Message, service_directory: &mut ServiceDirectory) -> Result<(), CommandError>;
+}
+
+#[async_trait]
+impl<T: LegacyCommand> Command for T {
+    async fn run(&self, context: CommandContext<'_, '_>) -> Result<(), CommandError> {
+        let CommandContext { message, services, .. } = context;
+        self.execute(message, services).await
+    }
+}
+
+/// Commands that know how they want to be registered.
+///
+/// This is implemented by [`#[bpp_command]`](crate::bpp_command) and used by [`export_commands!`](crate::export_commands).
+pub trait CommandDefinition: Command {
+    fn name() -> &'static str;
+
+    fn aliases() -> &'static [&'static str];
+
+    fn meta() -> CommandMeta {
+        CommandMeta::default()
+    }
+}
+
 /// Types that implement this trait register commands.
 pub trait CommandRegistrar {
     fn register_command(&mut self, name: &str, aliases: &[&str], command: Box<dyn Command>);
+
+    /// Registers a command together with its metadata, returning whether the command was registered.
+    ///
+    /// Registrars that have no use for metadata can rely on the default implementation, which discards it.
+    fn register_command_with_meta(&mut self, name: &str, aliases: &[&str], command: Box<dyn Command>, _meta: CommandMeta) -> bool {
+        self.register_command(name, aliases, command);
+        true
+    }
+
+    /// Registers a command with nested subcommands, returning whether the group was registered.
+    ///
+    /// The default implementation is meant for registrars without support for subcommands: it only
+    /// registers the group's own command, if it has one, and drops the subcommands.
+    fn register_group(&mut self, group: CommandGroup) -> bool {
+        match group.command {
+            Some(command) => {
+                let aliases: Vec<&str> = group.aliases.iter().map(|alias| alias.as_str()).collect();
+                self.register_command_with_meta(&group.name, &aliases, command, group.meta)
+            }
+            None => false,
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,289 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use tonic::transport::Channel;
+
+use crate::args::FromArgument;
+use crate::structs::{CommandUser, ConversionError, MissingTimestampPolicy};
+use crate::userservice::user_service_client::UserServiceClient;
+use crate::userservice::{BppUser, UserRequest};
+
+/// The number of candidates listed when a lookup is ambiguous.
+const MAX_CANDIDATES: usize = 5;
+
+/// An error raised while looking up a user.
+#[derive(Debug)]
+pub enum UserLookupError {
+    NotFound {
+        query: String,
+    },
+    Ambiguous {
+        query: String,
+        candidates: String,
+    },
+    InvalidUser {
+        source: ConversionError,
+    },
+    ServiceFailure {
+        status: Box<tonic::Status>,
+    },
+}
+
+impl fmt::Display for UserLookupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UserLookupError::NotFound { query } => write!(f, "No user matches '{}'", query),
+            UserLookupError::Ambiguous { query, candidates } => {
+                write!(f, "'{}' matches multiple users: {}", query, candidates)
+            }
+            UserLookupError::InvalidUser { source } => write!(f, "{}", source),
+            UserLookupError::ServiceFailure { status } => {
+                write!(f, "A service call failed: {}", status)
+            }
+        }
+    }
+}
+
+impl Error for UserLookupError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            UserLookupError::InvalidUser { source } => Some(source),
+            UserLookupError::ServiceFailure { status } => Some(status.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConversionError> for UserLookupError {
+    fn from(source: ConversionError) -> Self {
+        UserLookupError::InvalidUser { source }
+    }
+}
+
+impl From<tonic::Status> for UserLookupError {
+    fn from(status: tonic::Status) -> Self {
+        UserLookupError::ServiceFailure {
+            status: Box::new(status),
+        }
+    }
+}
+
+/// A reference to a user as written in a command, such as `@ByersPlusPlus` or a channel id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserRef {
+    /// A YouTube channel id, such as `UCxxxxxxxxxxxxxxxxxxxxxx`
+    ChannelId(String),
+    /// A (partial) display name, without the leading `@`
+    Name(String),
+}
+
+impl UserRef {
+    /// Whether `text` has the shape of a YouTube channel id.
+    fn is_channel_id(text: &str) -> bool {
+        text.len() == 24 && text.starts_with("UC") && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    /// The text the user was referred to by.
+    pub fn query(&self) -> &str {
+        match self {
+            UserRef::ChannelId(channel_id) => channel_id,
+            UserRef::Name(name) => name,
+        }
+    }
+}
+
+impl FromArgument for UserRef {
+    fn from_argument(argument: &str) -> Result<Self, String> {
+        if let Some(name) = argument.strip_prefix('@') {
+            if name.is_empty() {
+                return Err("a user name or channel id".to_string());
+            }
+            return Ok(UserRef::Name(name.to_string()));
+        }
+        if UserRef::is_channel_id(argument) {
+            return Ok(UserRef::ChannelId(argument.to_string()));
+        }
+        Ok(UserRef::Name(argument.to_string()))
+    }
+}
+
+/// Finds the user in `users` that `name` refers to, in the steps described by [`UserResolver::resolve`].
+fn match_name<'a>(users: &'a [BppUser], name: &str) -> Result<&'a BppUser, UserLookupError> {
+    let lowercase_name = name.to_lowercase();
+    let steps: [&dyn Fn(&BppUser) -> bool; 3] = [
+        &|user| user.channel_id == name,
+        &|user| user.display_name.to_lowercase() == lowercase_name,
+        &|user| user.display_name.to_lowercase().contains(&lowercase_name),
+    ];
+    for matches in steps.iter() {
+        let mut found: Vec<&BppUser> = users.iter().filter(|user| matches(user)).collect();
+        match found.len() {
+            0 => continue,
+            1 => return Ok(found.remove(0)),
+            _ => {
+                let mut candidates: Vec<&str> = found.iter().take(MAX_CANDIDATES).map(|user| user.display_name.as_str()).collect();
+                if found.len() > MAX_CANDIDATES {
+                    candidates.push("…");
+                }
+                return Err(UserLookupError::Ambiguous {
+                    query: name.to_string(),
+                    candidates: candidates.join(", "),
+                });
+            }
+        }
+    }
+    Err(UserLookupError::NotFound { query: name.to_string() })
+}
+
+/// Looks up the users that commands refer to through the userservice.
+///
+/// The userservice cannot search users by name, so the first lookup by name downloads every user.
+/// That list is kept and shared by clones of the resolver, so later lookups by name do not see
+/// users that were added or renamed in the meantime. Use a new resolver for every command invocation.
+#[derive(Clone)]
+pub struct UserResolver {
+    client: UserServiceClient<Channel>,
+    policy: MissingTimestampPolicy,
+    /// Every user of the userservice, fetched by the first lookup by name
+    users: Arc<tokio::sync::Mutex<Option<Arc<Vec<BppUser>>>>>,
+}
+
+impl UserResolver {
+    pub fn new(client: UserServiceClient<Channel>) -> Self {
+        UserResolver {
+            client,
+            policy: MissingTimestampPolicy::default(),
+            users: Arc::new(tokio::sync::Mutex::new(None)),
+        }
+    }
+
+    /// Sets how user records without timestamps are converted.
+    pub fn with_policy(mut self, policy: MissingTimestampPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Finds the user `user_ref` refers to.
+    ///
+    /// Channel ids are looked up directly. Names are matched against channel ids first, then against
+    /// display names ignoring case, and finally against parts of display names. If several users
+    /// match at the first step that finds any, the lookup fails with [`UserLookupError::Ambiguous`].
+    ///
+    /// Looking up a name fetches the whole user list if this resolver has not done so yet.
+    pub async fn resolve(&self, user_ref: &UserRef) -> Result<CommandUser, UserLookupError> {
+        match user_ref {
+            UserRef::ChannelId(channel_id) => self.resolve_channel_id(channel_id).await,
+            UserRef::Name(name) => self.resolve_name(name).await,
+        }
+    }
+
+    async fn resolve_channel_id(&self, channel_id: &str) -> Result<CommandUser, UserLookupError> {
+        let request = UserRequest { channel_id: channel_id.to_string() };
+        match self.client.clone().get_user(request).await {
+            Ok(user) => self.convert(user.into_inner()),
+            Err(status) if status.code() == tonic::Code::NotFound => Err(UserLookupError::NotFound { query: channel_id.to_string() }),
+            Err(status) => Err(status.into()),
+        }
+    }
+
+    async fn resolve_name(&self, name: &str) -> Result<CommandUser, UserLookupError> {
+        let users = self.all_users().await?;
+        self.convert(match_name(&users, name)?.clone())
+    }
+
+    async fn all_users(&self) -> Result<Arc<Vec<BppUser>>, UserLookupError> {
+        let mut users = self.users.lock().await;
+        if let Some(users) = users.as_ref() {
+            return Ok(users.clone());
+        }
+        let fetched = Arc::new(self.client.clone().get_users(()).await?.into_inner().users);
+        *users = Some(fetched.clone());
+        Ok(fetched)
+    }
+
+    fn convert(&self, user: BppUser) -> Result<CommandUser, UserLookupError> {
+        Ok(CommandUser::from_bpp_user(user, self.policy)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(channel_id: &str, display_name: &str) -> BppUser {
+        BppUser { channel_id: channel_id.to_string(), display_name: display_name.to_string(), ..BppUser::default() }
+    }
+
+    fn users() -> Vec<BppUser> {
+        vec![
+            user("UCaaaaaaaaaaaaaaaaaaaaaa", "Alice"),
+            user("UCbbbbbbbbbbbbbbbbbbbbbb", "alice"),
+            user("UCcccccccccccccccccccccc", "Bob"),
+            user("UCdddddddddddddddddddddd", "Bobby Tables"),
+            user("UCeeeeeeeeeeeeeeeeeeeeee", "Carol"),
+        ]
+    }
+
+    fn matched(name: &str) -> Result<String, UserLookupError> {
+        match_name(&users(), name).map(|user| user.channel_id.clone())
+    }
+
+    #[test]
+    fn mentions_are_names() {
+        assert_eq!(UserRef::from_argument("@Alice"), Ok(UserRef::Name("Alice".to_string())));
+        assert_eq!(UserRef::from_argument("@UCaaaaaaaaaaaaaaaaaaaaaa"), Ok(UserRef::Name("UCaaaaaaaaaaaaaaaaaaaaaa".to_string())));
+        assert!(UserRef::from_argument("@").is_err());
+    }
+
+    #[test]
+    fn channel_ids_are_recognized() {
+        assert_eq!(UserRef::from_argument("UC-_aaaaaaaaaaaaaaaaaaaa"), Ok(UserRef::ChannelId("UC-_aaaaaaaaaaaaaaaaaaaa".to_string())));
+        // too short, a different prefix or characters channel ids don't have
+        assert_eq!(UserRef::from_argument("UCaaaaaaaaaaaaaaaaaaaaa"), Ok(UserRef::Name("UCaaaaaaaaaaaaaaaaaaaaa".to_string())));
+        assert_eq!(UserRef::from_argument("UXaaaaaaaaaaaaaaaaaaaaaa"), Ok(UserRef::Name("UXaaaaaaaaaaaaaaaaaaaaaa".to_string())));
+        assert_eq!(UserRef::from_argument("UCaaaaaaaaaaaaaaaaaaaaa!"), Ok(UserRef::Name("UCaaaaaaaaaaaaaaaaaaaaa!".to_string())));
+    }
+
+    #[test]
+    fn other_arguments_are_names() {
+        let user_ref = UserRef::from_argument("Bob").unwrap();
+        assert_eq!(user_ref, UserRef::Name("Bob".to_string()));
+        assert_eq!(user_ref.query(), "Bob");
+    }
+
+    #[test]
+    fn channel_ids_match_exactly() {
+        assert_eq!(matched("UCbbbbbbbbbbbbbbbbbbbbbb").unwrap(), "UCbbbbbbbbbbbbbbbbbbbbbb");
+    }
+
+    #[test]
+    fn display_names_match_ignoring_case_before_parts_of_names() {
+        assert_eq!(matched("BOB").unwrap(), "UCcccccccccccccccccccccc");
+        assert_eq!(matched("carol").unwrap(), "UCeeeeeeeeeeeeeeeeeeeeee");
+        assert_eq!(matched("tables").unwrap(), "UCdddddddddddddddddddddd");
+    }
+
+    #[test]
+    fn several_matches_at_one_step_are_ambiguous() {
+        assert!(matches!(
+            matched("ALICE"),
+            Err(UserLookupError::Ambiguous { query, candidates }) if query == "ALICE" && candidates == "Alice, alice"
+        ));
+        assert!(matches!(matched("o"), Err(UserLookupError::Ambiguous { candidates, .. }) if candidates == "Bob, Bobby Tables, Carol"));
+    }
+
+    #[test]
+    fn ambiguous_matches_list_a_limited_number_of_candidates() {
+        let users: Vec<BppUser> = (0..7).map(|i| user(&format!("channel{}", i), &format!("user{}", i))).collect();
+        assert!(matches!(
+            match_name(&users, "user"),
+            Err(UserLookupError::Ambiguous { candidates, .. }) if candidates == "user0, user1, user2, user3, user4, …"
+        ));
+    }
+
+    #[test]
+    fn unknown_names_are_not_found() {
+        assert!(matches!(matched("dave"), Err(UserLookupError::NotFound { query }) if query == "dave"));
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+use custom_error::custom_error;
+
+/// Revision of the plugin ABI.
+///
+/// This has to be bumped whenever a type that crosses the plugin boundary changes in a way that
+/// breaks already compiled plugins, independent of the crate version.
+pub const ABI_REVISION: u32 = 2;
+
+/// The ABI version of this build of the API, as embedded into plugins by [`export_command!`](crate::export_command).
+pub const CORE_ABI_VERSION: AbiVersion = AbiVersion {
+    major: parse_component(env!("CARGO_PKG_VERSION_MAJOR")),
+    minor: parse_component(env!("CARGO_PKG_VERSION_MINOR")),
+    patch: parse_component(env!("CARGO_PKG_VERSION_PATCH")),
+    abi_revision: ABI_REVISION,
+};
+
+custom_error! { pub Incompatibility
+    AbiRevision{plugin: u32, host: u32} = "the plugin uses ABI revision {plugin}, but the host uses ABI revision {host}",
+    MajorVersion{plugin: AbiVersion, host: AbiVersion} = "the plugin was built against API {plugin}, which is a different major version than the host's API {host}",
+    MinorVersion{plugin: AbiVersion, host: AbiVersion} = "the plugin was built against API {plugin}, which is a different pre-release minor version than the host's API {host}",
+    NewerVersion{plugin: AbiVersion, host: AbiVersion} = "the plugin requires API {plugin}, but the host only provides API {host}"
+}
+
+/// A structured version of the API a plugin was built against.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbiVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// See [`ABI_REVISION`]
+    pub abi_revision: u32,
+}
+
+impl AbiVersion {
+    /// Checks whether a plugin built against `plugin` can be loaded by a host using this version.
+    ///
+    /// Versions are compatible if they share the ABI revision and follow semver compatibility:
+    /// the major version has to match, and for `0.x` versions the minor version has to match too.
+    /// Plugins built against a newer minor version than the host are refused. Patch versions are ignored.
+    pub fn check_compatible(&self, plugin: &AbiVersion) -> Result<(), Incompatibility> {
+        if plugin.abi_revision != self.abi_revision {
+            return Err(Incompatibility::AbiRevision {
+                plugin: plugin.abi_revision,
+                host: self.abi_revision,
+            });
+        }
+        if plugin.major != self.major {
+            return Err(Incompatibility::MajorVersion { plugin: *plugin, host: *self });
+        }
+        if self.major == 0 && plugin.minor != self.minor {
+            return Err(Incompatibility::MinorVersion { plugin: *plugin, host: *self });
+        }
+        if plugin.minor > self.minor {
+            return Err(Incompatibility::NewerVersion { plugin: *plugin, host: *self });
+        }
+        Ok(())
+    }
+
+    pub fn is_compatible(&self, plugin: &AbiVersion) -> bool {
+        self.check_compatible(plugin).is_ok()
+    }
+}
+
+impl fmt::Display for AbiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{} (ABI revision {})", self.major, self.minor, self.patch, self.abi_revision)
+    }
+}
+
+/// Parses a version component at compile time.
+const fn parse_component(component: &str) -> u32 {
+    let bytes = component.as_bytes();
+    let mut result = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        result = result * 10 + (bytes[i] - b'0') as u32;
+        i += 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(major: u32, minor: u32, patch: u32) -> AbiVersion {
+        AbiVersion { major, minor, patch, abi_revision: ABI_REVISION }
+    }
+
+    #[test]
+    fn same_version_is_compatible() {
+        assert!(version(1, 2, 3).is_compatible(&version(1, 2, 3)));
+        assert!(CORE_ABI_VERSION.is_compatible(&CORE_ABI_VERSION));
+    }
+
+    #[test]
+    fn patch_versions_are_ignored() {
+        assert!(version(1, 2, 3).is_compatible(&version(1, 2, 9)));
+        assert!(version(0, 3, 3).is_compatible(&version(0, 3, 0)));
+    }
+
+    #[test]
+    fn abi_revision_has_to_match() {
+        let plugin = AbiVersion { abi_revision: ABI_REVISION + 1, ..version(1, 2, 3) };
+        assert!(matches!(version(1, 2, 3).check_compatible(&plugin), Err(Incompatibility::AbiRevision { .. })));
+    }
+
+    #[test]
+    fn major_version_has_to_match() {
+        assert!(matches!(version(2, 0, 0).check_compatible(&version(1, 0, 0)), Err(Incompatibility::MajorVersion { .. })));
+        assert!(matches!(version(1, 0, 0).check_compatible(&version(0, 9, 0)), Err(Incompatibility::MajorVersion { .. })));
+    }
+
+    #[test]
+    fn pre_release_minor_version_has_to_match() {
+        assert!(matches!(version(0, 3, 0).check_compatible(&version(0, 2, 0)), Err(Incompatibility::MinorVersion { .. })));
+        assert!(matches!(version(0, 3, 0).check_compatible(&version(0, 4, 0)), Err(Incompatibility::MinorVersion { .. })));
+    }
+
+    #[test]
+    fn older_minor_version_is_compatible() {
+        assert!(version(1, 4, 0).is_compatible(&version(1, 2, 7)));
+    }
+
+    #[test]
+    fn newer_minor_version_is_refused() {
+        assert!(matches!(version(1, 2, 0).check_compatible(&version(1, 3, 0)), Err(Incompatibility::NewerVersion { .. })));
+    }
+
+    #[test]
+    fn components_are_parsed() {
+        assert_eq!(parse_component("0"), 0);
+        assert_eq!(parse_component("42"), 42);
+    }
+}